@@ -0,0 +1,59 @@
+#![feature(coroutines, test)]
+extern crate test;
+
+use std::time::Duration;
+
+use rustsim::{Action, GenBoxed, Key, KeyBufferPool, Simulation};
+use test::Bencher;
+
+const CONSUMERS: usize = 8;
+const ROUNDS: u64 = 100;
+
+fn consumer() -> GenBoxed<()> {
+    Box::new(
+        #[coroutine]
+        |_| loop {
+            yield Action::Passivate;
+        },
+    )
+}
+
+// Wakes every consumer once per simulated second.
+fn producer(consumers: Vec<Key>, pool: Option<KeyBufferPool>) -> GenBoxed<()> {
+    Box::new(
+        #[coroutine]
+        move |_| loop {
+            yield Action::Hold(Duration::from_secs(1));
+            let mut keys = match &pool {
+                Some(pool) => pool.take(),
+                None => Vec::with_capacity(consumers.len()),
+            };
+            keys.extend_from_slice(&consumers);
+            yield Action::ActivateMany(keys);
+        },
+    )
+}
+
+fn run(pooled: bool) {
+    let mut simulation = Simulation::default();
+    let consumers: Vec<Key> = (0..CONSUMERS)
+        .map(|_| simulation.add_generator(consumer()))
+        .collect();
+    let pool = pooled.then(|| simulation.key_buffers());
+    let producer = simulation.add_generator(producer(consumers.clone(), pool));
+    for key in consumers {
+        simulation.schedule_now(key);
+    }
+    simulation.schedule_now(producer);
+    simulation.run_with_limit(Duration::from_secs(ROUNDS));
+}
+
+#[bench]
+fn activate_many_fresh_vec(b: &mut Bencher) {
+    b.iter(|| run(false));
+}
+
+#[bench]
+fn activate_many_pooled_vec(b: &mut Bencher) {
+    b.iter(|| run(true));
+}
@@ -0,0 +1,61 @@
+#![feature(coroutines, test)]
+extern crate test;
+
+use std::time::Duration;
+
+use rustsim::{Action, GenBoxed, Key, Simulation};
+use test::Bencher;
+
+const CONSUMERS: usize = 3;
+const ROUNDS: u64 = 1000;
+
+fn consumer() -> GenBoxed<()> {
+    Box::new(
+        #[coroutine]
+        |_| loop {
+            yield Action::Passivate;
+        },
+    )
+}
+
+// Wakes every consumer once per simulated second.
+fn producer(consumers: Vec<Key>, small: bool) -> GenBoxed<()> {
+    Box::new(
+        #[coroutine]
+        move |_| loop {
+            yield Action::Hold(Duration::from_secs(1));
+            if small {
+                let mut keys = [None; 4];
+                for (slot, &key) in keys.iter_mut().zip(&consumers) {
+                    *slot = Some(key);
+                }
+                yield Action::ActivateSmall(keys);
+            } else {
+                yield Action::ActivateMany(consumers.clone());
+            }
+        },
+    )
+}
+
+fn run(small: bool) {
+    let mut simulation = Simulation::default();
+    let consumers: Vec<Key> = (0..CONSUMERS)
+        .map(|_| simulation.add_generator(consumer()))
+        .collect();
+    let producer = simulation.add_generator(producer(consumers.clone(), small));
+    for key in consumers {
+        simulation.schedule_now(key);
+    }
+    simulation.schedule_now(producer);
+    simulation.run_with_limit(Duration::from_secs(ROUNDS));
+}
+
+#[bench]
+fn small_fan_out_activate_many(b: &mut Bencher) {
+    b.iter(|| run(false));
+}
+
+#[bench]
+fn small_fan_out_activate_small(b: &mut Bencher) {
+    b.iter(|| run(true));
+}
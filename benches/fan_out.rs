@@ -0,0 +1,68 @@
+#![feature(coroutines, test)]
+extern crate test;
+
+use std::time::Duration;
+
+use rustsim::{Action, GenBoxed, Key, Simulation};
+use test::Bencher;
+
+const CONSUMERS: usize = 64;
+const ROUNDS: u64 = 100;
+
+fn consumer() -> GenBoxed<()> {
+    Box::new(
+        #[coroutine]
+        |_| loop {
+            yield Action::Passivate;
+        },
+    )
+}
+
+// Wakes every consumer once per simulated second, one `ActivateOne` per consumer so
+// the producer is rescheduled after each of them.
+fn one_by_one(consumers: Vec<Key>) -> GenBoxed<()> {
+    Box::new(
+        #[coroutine]
+        move |_| loop {
+            yield Action::Hold(Duration::from_secs(1));
+            // Cloned like the `ActivateMany` below, a borrow can't be held across yields.
+            for consumer in consumers.clone() {
+                yield Action::ActivateOne(consumer);
+            }
+        },
+    )
+}
+
+// Same with a single `ActivateMany`, the producer is rescheduled once per round.
+fn all_at_once(consumers: Vec<Key>) -> GenBoxed<()> {
+    Box::new(
+        #[coroutine]
+        move |_| loop {
+            yield Action::Hold(Duration::from_secs(1));
+            yield Action::ActivateMany(consumers.clone());
+        },
+    )
+}
+
+fn run(producer: fn(Vec<Key>) -> GenBoxed<()>) {
+    let mut simulation = Simulation::default();
+    let consumers: Vec<Key> = (0..CONSUMERS)
+        .map(|_| simulation.add_generator(consumer()))
+        .collect();
+    let producer = simulation.add_generator(producer(consumers.clone()));
+    for key in consumers {
+        simulation.schedule_now(key);
+    }
+    simulation.schedule_now(producer);
+    simulation.run_with_limit(Duration::from_secs(ROUNDS));
+}
+
+#[bench]
+fn fan_out_activate_one(b: &mut Bencher) {
+    b.iter(|| run(one_by_one));
+}
+
+#[bench]
+fn fan_out_activate_many(b: &mut Bencher) {
+    b.iter(|| run(all_at_once));
+}
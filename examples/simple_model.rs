@@ -68,7 +68,7 @@ fn main() {
 // 
 // A short explanation of both entities are explained above main but a line by line explanation is also included in the body of this function.
 fn entity_a(shared_state: Rc<Cell<State>>, entity_b_key: StateKey<Option<Key>>, entity_states_key: StateKey<Passivated>) -> GenBoxed<()> {
-    Box::new(move |_|{
+    Box::new(#[coroutine] move |_|{
         // Temporarily extract the state leaving a default one in place
         let mut state = shared_state.take();
 
@@ -139,7 +139,7 @@ fn entity_a(shared_state: Rc<Cell<State>>, entity_b_key: StateKey<Option<Key>>,
 // It's almost the same as Entity A with the difference that it can take Entity A Key directly without using the simulation state
 // It's body it's almost identical with the exception that it will first do a Passivate then it's normal execution
 fn entity_b(shared_state: Rc<Cell<State>>, entity_a_key: Key, entity_states_key: StateKey<Passivated>) -> GenBoxed<()> {
-    Box::new(move |_| {
+    Box::new(#[coroutine] move |_| {
 
         let mut state = shared_state.take();
 
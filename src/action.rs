@@ -0,0 +1,114 @@
+//! Short constructors for the [Action]s yielded by generators.
+
+use std::time::Duration;
+
+use crate::{Action, TypedKey};
+
+/// Wait for `duration` before being resumed.
+///
+/// ```
+/// #![feature(coroutines)]
+/// use std::time::Duration;
+/// use rustsim::{action, GenBoxed, Simulation};
+///
+/// let gen: GenBoxed<()> = Box::new(#[coroutine] |_| {
+///     yield action::hold(Duration::from_secs(5));
+/// });
+/// let mut simulation = Simulation::default();
+/// let key = simulation.add_generator(gen);
+/// simulation.schedule_now(key);
+/// simulation.run_until_empty();
+/// assert_eq!(Duration::from_secs(5), simulation.time());
+/// ```
+#[inline]
+#[must_use]
+pub fn hold(duration: Duration) -> Action {
+    Action::Hold(duration)
+}
+
+/// Stop until another entity activates this one.
+///
+/// ```
+/// #![feature(coroutines)]
+/// use rustsim::{action, EntityState, GenBoxed, Simulation};
+///
+/// let gen: GenBoxed<()> = Box::new(#[coroutine] |_| {
+///     yield action::passivate();
+/// });
+/// let mut simulation = Simulation::default();
+/// let key = simulation.add_generator(gen);
+/// simulation.schedule_now(key);
+/// simulation.run_until_empty();
+/// assert_eq!(Some(EntityState::Passive), simulation.entity_state(key));
+/// ```
+#[inline]
+#[must_use]
+pub fn passivate() -> Action {
+    Action::Passivate
+}
+
+/// Let other entities scheduled at the current time run before resuming.
+///
+/// Same as holding for [`Duration::ZERO`].
+///
+/// ```
+/// #![feature(coroutines)]
+/// use std::time::Duration;
+/// use rustsim::{action, GenBoxed, Simulation};
+///
+/// let gen: GenBoxed<()> = Box::new(#[coroutine] |_| {
+///     for _ in 0..3 {
+///         yield action::yield_now();
+///     }
+/// });
+/// let mut simulation = Simulation::default();
+/// let key = simulation.add_generator(gen);
+/// simulation.schedule_now(key);
+/// simulation.run_until_empty();
+/// assert_eq!(Duration::ZERO, simulation.time());
+/// ```
+#[inline]
+#[must_use]
+pub fn yield_now() -> Action {
+    Action::Hold(Duration::ZERO)
+}
+
+/// Activate the passive entity of `key`.
+///
+/// Taking a [TypedKey] lets models require the key of a given kind of entity,
+/// see [`Simulation::add_generator_typed`](crate::Simulation::add_generator_typed).
+///
+/// ```
+/// #![feature(coroutines)]
+/// use rustsim::{action, EntityState, GenBoxed, Simulation, TypedKey};
+///
+/// struct Server;
+///
+/// fn wake_server(server: TypedKey<Server>) -> GenBoxed<()> {
+///     Box::new(#[coroutine] move |_| {
+///         yield action::activate(server);
+///     })
+/// }
+///
+/// let mut simulation = Simulation::default();
+/// let server = simulation.add_generator_typed::<Server>(Box::new(#[coroutine] |_| {
+///     yield action::passivate();
+/// }));
+/// let caller = simulation.add_generator(wake_server(server));
+/// simulation.schedule_now(server.key());
+/// simulation.step();
+/// simulation.schedule_now(caller);
+/// simulation.run_until_empty();
+/// assert_eq!(Some(EntityState::Completed), simulation.entity_state(server.key()));
+/// ```
+#[inline]
+#[must_use]
+pub fn activate<K>(key: TypedKey<K>) -> Action {
+    Action::ActivateOne(key.key())
+}
+
+/// Activate the passive entities of `keys`, all of the same kind.
+#[must_use]
+pub fn activate_many<K>(keys: &[TypedKey<K>]) -> Action {
+    Action::ActivateMany(keys.iter().map(|key| key.key()).collect())
+}
@@ -1,40 +1,123 @@
 use crate::{keys::Key, Action, GenBoxed};
 use std::ops::CoroutineState;
 use std::pin::Pin;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum EntityState {
     Passive,
     Active,
+    /// The generator finished, its slot is kept until it is reaped.
+    Completed,
 }
 
-pub struct Container<R> {
-    pub(crate) inner: Vec<Option<(GenBoxed<R>, EntityState)>>,
+/// Why an entity was resumed, see [`Simulation::last_wake_reason`](crate::Simulation::last_wake_reason).
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum WakeReason {
+    /// The time it was scheduled at came, the end of a hold or an event scheduled
+    /// from outside the entity.
+    #[default]
+    HoldExpired,
+    /// Another entity activated it.
+    Activated,
+    /// Another entity cancelled its pending event and it was activated again.
+    Interrupted,
 }
 
-impl<R> Default for Container<R>
+/// Number of entities in each [EntityState].
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct StateCounts {
+    pub active: usize,
+    pub passive: usize,
+    pub completed: usize,
+}
+
+pub(crate) struct Slot<R, O> {
+    /// `None` once the generator completed.
+    gen: Option<GenBoxed<R, O>>,
+    state: EntityState,
+    /// Number of times the generator has been resumed.
+    steps: u64,
+    /// Simulation time at which the generator completed.
+    completed_at: Option<Duration>,
+    /// Value returned by the generator, until it is taken.
+    output: Option<O>,
+    /// Why the generator will be resumed next.
+    next_wake: WakeReason,
+    /// Why the generator was resumed last, `None` before the first resume.
+    last_wake: Option<WakeReason>,
+}
+
+pub struct Container<R, O = ()> {
+    pub(crate) inner: Vec<Option<Slot<R, O>>>,
+    /// Ids of reaped slots, reused by the next insertions.
+    free: Vec<usize>,
+}
+
+impl<R, O> Default for Container<R, O>
 where
     R: 'static,
+    O: 'static,
 {
     fn default() -> Self {
         Self {
             inner: Default::default(),
+            free: Vec::new(),
         }
     }
 }
 
-impl<R> Container<R>
+impl<R, O> Container<R, O>
 where
     R: 'static,
+    O: 'static,
 {
-    pub fn add_generator(&mut self, gen: GenBoxed<R>) -> Key {
-        let key = Key::new(self.inner.len());
-        self.inner.push(Some((gen, EntityState::Active)));
-        key
+    /// Creates an empty container with space for at least `capacity` generators.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            inner: Vec::with_capacity(capacity),
+            free: Vec::new(),
+        }
     }
 
-    #[allow(dead_code)]
-    pub fn remove(&mut self, key: Key) -> Option<(GenBoxed<R>, EntityState)> {
+    pub fn add_generator(&mut self, gen: GenBoxed<R, O>) -> Key {
+        self.insert(gen, EntityState::Active)
+    }
+
+    /// Add a generator that starts in `state`.
+    ///
+    /// The slot of a reaped generator is reused if there is one, so its key is handed out
+    /// again. Otherwise keys are handed out in increasing order.
+    pub fn insert(&mut self, gen: GenBoxed<R, O>, state: EntityState) -> Key {
+        let slot = Some(Slot {
+            gen: Some(gen),
+            state,
+            steps: 0,
+            completed_at: None,
+            output: None,
+            next_wake: WakeReason::default(),
+            last_wake: None,
+        });
+        match self.free.pop() {
+            Some(id) => {
+                self.inner[id] = slot;
+                Key::new(id)
+            }
+            None => {
+                self.inner.push(slot);
+                Key::new(self.inner.len() - 1)
+            }
+        }
+    }
+
+    /// Returns the key the next inserted generator will get.
+    #[must_use]
+    pub fn next_key(&self) -> Key {
+        Key::new(self.free.last().copied().unwrap_or(self.inner.len()))
+    }
+
+    pub fn remove(&mut self, key: Key) -> Option<(GenBoxed<R, O>, EntityState)> {
         // if self.inner.get(key.id).is_some() {
         //     self.inner[key.id].take()
         // } else {
@@ -44,7 +127,78 @@ where
         // Another way of doing the above added in rust 1.62
         // self.inner.get(key.id).is_some().then_some(self.inner[key.id].take()).flatten()
 
-        self.inner.get_mut(key.id).and_then(Option::take)
+        self.inner
+            .get_mut(key.id)
+            .and_then(Option::take)
+            .and_then(|slot| slot.gen.map(|gen| (gen, slot.state)))
+    }
+
+    /// Drop the generator of `key` and mark it as [`EntityState::Completed`] at `time`.
+    ///
+    /// The state, step count and `output` stay available until the slot is
+    /// [reaped](Container::drain_completed).
+    pub fn complete(&mut self, key: Key, time: Duration, output: O) {
+        if let Some(slot) = self.inner.get_mut(key.id).and_then(Option::as_mut) {
+            slot.gen = None;
+            slot.state = EntityState::Completed;
+            slot.completed_at = Some(time);
+            slot.output = Some(output);
+        }
+    }
+
+    /// Take the value returned by the generator of `key`.
+    pub fn take_output(&mut self, key: Key) -> Option<O> {
+        self.inner
+            .get_mut(key.id)
+            .and_then(Option::as_mut)
+            .and_then(|slot| slot.output.take())
+    }
+
+    /// Free the slots of every completed generator, returning their keys and completion times.
+    pub fn drain_completed(&mut self) -> Vec<(Key, Duration)> {
+        let mut drained = Vec::new();
+        for (id, slot) in self.inner.iter_mut().enumerate() {
+            if let Some(Slot {
+                completed_at: Some(time),
+                ..
+            }) = slot
+            {
+                let time = *time;
+                drained.push((Key::new(id), time));
+                *slot = None;
+                self.free.push(id);
+            }
+        }
+        drained
+    }
+
+    /// Returns a description of the first inconsistency found between the slots and
+    /// the free list, if any.
+    pub fn find_inconsistency(&self) -> Option<String> {
+        for (id, slot) in self.inner.iter().enumerate() {
+            let Some(slot) = slot else { continue };
+            if slot.gen.is_none() && slot.state != EntityState::Completed {
+                return Some(format!(
+                    "Entity ID = {} is {:?} but its generator completed",
+                    id, slot.state
+                ));
+            }
+            if slot.gen.is_some() && slot.state == EntityState::Completed {
+                return Some(format!(
+                    "Entity ID = {} is Completed but its generator can still be resumed",
+                    id
+                ));
+            }
+        }
+        self.free
+            .iter()
+            .find(|&&id| !matches!(self.inner.get(id), Some(None)))
+            .map(|id| {
+                format!(
+                    "Entity ID = {} is in the free list but its slot is in use",
+                    id
+                )
+            })
     }
 
     /// Returns the number of elements in the container.
@@ -59,28 +213,95 @@ where
         self.inner.is_empty()
     }
 
+    /// Returns an iterator over the keys of the generators that haven't completed.
+    pub fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| match slot {
+                Some(Slot { gen: Some(_), .. }) => Some(Key::new(id)),
+                _ => None,
+            })
+    }
+
+    /// Returns an iterator over the keys of the generators that completed and weren't reaped.
+    pub fn completed_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.inner
+            .iter()
+            .enumerate()
+            .filter_map(|(id, slot)| match slot {
+                Some(Slot {
+                    state: EntityState::Completed,
+                    ..
+                }) => Some(Key::new(id)),
+                _ => None,
+            })
+    }
+
+    /// Count the entities still in the container by their state.
+    #[must_use]
+    pub fn state_counts(&self) -> StateCounts {
+        self.inner
+            .iter()
+            .flatten()
+            .fold(StateCounts::default(), |mut counts, slot| {
+                match slot.state {
+                    EntityState::Active => counts.active += 1,
+                    EntityState::Passive => counts.passive += 1,
+                    EntityState::Completed => counts.completed += 1,
+                }
+                counts
+            })
+    }
+
     /// Advance the entity defined by `key`
     ///
     /// # Panics
     ///
     /// Panics when the key used was for an already extracted generator
     /// or if the generator has already completed its execution.
-    pub fn step_with(&mut self, key: Key, resume_with: R) -> CoroutineState<Action, ()> {
+    pub fn step_with(&mut self, key: Key, resume_with: R) -> CoroutineState<Action, O> {
         // Esto asume que los eventos nunca son borrados.
         // TODO: Confirmar esta asumpción.
 
-        let &mut (ref mut gen, _) = self
+        let slot = self
             .inner
             .get_mut(key.id)
             .and_then(Option::as_mut)
             .expect("entities shouldn't be removed from the container");
+        slot.steps += 1;
+        slot.last_wake = Some(std::mem::take(&mut slot.next_wake));
 
         // gen.step(resume_with)
-        let gen = gen.as_mut();
+        let gen = slot
+            .gen
+            .as_mut()
+            .expect("completed entities can't be resumed")
+            .as_mut();
         Pin::new(gen).resume(resume_with)
         // gen.resume_with(resume_with)
     }
 
+    /// Same as [`Container::step_with`] but a panic of the generator is caught and the
+    /// entity is removed, the panic payload is returned instead.
+    ///
+    /// The generator is assumed to be unwind safe: anything it shares with the rest of the
+    /// simulation, like an `Rc<RefCell<_>>`, may be left in an inconsistent state.
+    #[cfg(feature = "catch-panics")]
+    pub fn catch_step_with(
+        &mut self,
+        key: Key,
+        resume_with: R,
+    ) -> std::thread::Result<CoroutineState<Action, O>> {
+        use std::panic::{catch_unwind, AssertUnwindSafe};
+
+        let result = catch_unwind(AssertUnwindSafe(|| self.step_with(key, resume_with)));
+        if result.is_err() {
+            self.inner[key.id] = None;
+        }
+        result
+    }
+
     #[must_use]
     pub fn get_state(&self, key: Key) -> Option<&EntityState> {
         // if let Some(values) = self.inner.get(key.id) {
@@ -92,7 +313,7 @@ where
         self.inner
             .get(key.id)
             .and_then(Option::as_ref)
-            .map(|(_, state)| state)
+            .map(|slot| &slot.state)
     }
 
     #[must_use]
@@ -106,7 +327,42 @@ where
         self.inner
             .get_mut(key.id)
             .and_then(Option::as_mut)
-            .map(|&mut (_, ref mut state)| state)
+            .map(|slot| &mut slot.state)
+    }
+
+    /// Record that `key` was activated, unless its pending event was cancelled before
+    /// in which case it is resumed as [`WakeReason::Interrupted`].
+    pub fn mark_activated(&mut self, key: Key) {
+        if let Some(slot) = self.inner.get_mut(key.id).and_then(Option::as_mut) {
+            if slot.next_wake != WakeReason::Interrupted {
+                slot.next_wake = WakeReason::Activated;
+            }
+        }
+    }
+
+    /// Record that the pending event of `key` was cancelled.
+    pub fn mark_interrupted(&mut self, key: Key) {
+        if let Some(slot) = self.inner.get_mut(key.id).and_then(Option::as_mut) {
+            slot.next_wake = WakeReason::Interrupted;
+        }
+    }
+
+    /// Returns why the generator associated with `key` was resumed last.
+    #[must_use]
+    pub fn last_wake(&self, key: Key) -> Option<WakeReason> {
+        self.inner
+            .get(key.id)
+            .and_then(Option::as_ref)
+            .and_then(|slot| slot.last_wake)
+    }
+
+    /// Returns how many times the generator associated with `key` has been resumed.
+    #[must_use]
+    pub fn steps(&self, key: Key) -> Option<u64> {
+        self.inner
+            .get(key.id)
+            .and_then(Option::as_ref)
+            .map(|slot| slot.steps)
     }
 }
 
@@ -119,12 +375,11 @@ impl Container<()> {
 
 #[cfg(test)]
 mod test {
-    use std::time::Duration;
-
     use super::*;
 
     fn producer(kind: &'static str) -> GenBoxed<()> {
-        let gen = move |_| {
+        let gen = #[coroutine]
+        move |_| {
             println!("Iniciando {}", kind);
             // TODO: FIX THIS FUNCION. ESPECIFICAMENTE EL TIPO DE YIELD
             yield Action::Passivate;
@@ -143,10 +398,11 @@ mod test {
     }
 
     fn finite(name: &'static str, number_of_loops: u8) -> GenBoxed<()> {
-        let gen = move |_| {
+        let gen = #[coroutine]
+        move |_| {
             for i in 0..number_of_loops {
                 println!("Yield");
-                let _ = yield Action::Hold(Duration::ZERO);
+                yield Action::Hold(Duration::ZERO);
                 // co.hold(Duration::ZERO).await
                 println!("{} has yielded {} times", name, i + 1);
             }
@@ -156,7 +412,8 @@ mod test {
     }
 
     fn infinite(indentifier: usize) -> GenBoxed<()> {
-        let gen = move |_| {
+        let gen = #[coroutine]
+        move |_| {
             println!("This function is starting and will never complete");
             let mut i = 1;
             loop {
@@ -164,7 +421,7 @@ mod test {
                     "Infinite Generator N°{} is Yielding | It has Yielded {} times",
                     indentifier, i
                 );
-                let _ = yield Action::Hold(Duration::ZERO);
+                yield Action::Hold(Duration::ZERO);
                 // co.hold(Duration::ZERO).await;
                 i += 1;
             }
@@ -195,6 +452,13 @@ mod test {
         assert_eq!(4, container.len());
     }
 
+    #[test]
+    fn with_capacity_preallocates() {
+        let container = Container::<()>::with_capacity(64);
+        assert!(container.is_empty());
+        assert!(container.inner.capacity() >= 64);
+    }
+
     #[test]
     fn generators_can_be_resumed() {
         let mut container = Container::default();
@@ -203,9 +467,36 @@ mod test {
         
         while let CoroutineState::Yielded(_) = container.step_with(finite_key, ()) {}
 
+        // 3 holds plus the resume that completes the generator.
+        assert_eq!(Some(4), container.steps(finite_key));
+
         // Uncommenting the following line will cause the test to fail.
         // container.step_with(finite_key, ());
         // This is because when a generator completes, to say, the original function end its excecution
         // The generator cannot be resumed again and it's an error to do so.
     }   
+
+    #[test]
+    fn completed_generators_keep_their_state_until_reaped() {
+        let mut container = Container::default();
+        let finished = container.add_generator(finite("A", 1));
+        let running = container.add_generator(infinite(1));
+        container.step(finished);
+        container.step(running);
+        assert!(matches!(
+            container.step(finished),
+            CoroutineState::Complete(())
+        ));
+        container.complete(finished, Duration::from_secs(1), ());
+
+        assert_eq!(Some(&EntityState::Completed), container.get_state(finished));
+        assert_eq!(Some(2), container.steps(finished));
+        assert_eq!(vec![running], container.keys().collect::<Vec<_>>());
+        assert_eq!(1, container.state_counts().completed);
+
+        assert_eq!(1, container.drain_completed().len());
+        assert_eq!(None, container.get_state(finished));
+        assert_eq!(Some(&EntityState::Active), container.get_state(running));
+        assert_eq!(0, container.drain_completed().len());
+    }
 }
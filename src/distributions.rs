@@ -0,0 +1,272 @@
+use std::error::Error;
+use std::f64::consts::TAU;
+use std::fmt;
+use std::time::Duration;
+
+use crate::rng::SimRng;
+
+/// Error returned when a distribution is constructed with invalid parameters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistributionError {
+    /// The rate must be finite and greater than zero.
+    InvalidRate(f64),
+    /// The mean must be finite.
+    InvalidMean(f64),
+    /// The standard deviation must be finite and not negative.
+    InvalidStdDev(f64),
+    /// The lower bound of a range must be smaller than the upper bound.
+    EmptyRange(Duration, Duration),
+}
+
+impl fmt::Display for DistributionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DistributionError::InvalidRate(rate) => {
+                write!(f, "rate must be finite and greater than zero, got {}", rate)
+            }
+            DistributionError::InvalidMean(mean) => {
+                write!(f, "mean must be finite, got {}", mean)
+            }
+            DistributionError::InvalidStdDev(std_dev) => {
+                write!(
+                    f,
+                    "standard deviation must be finite and not negative, got {}",
+                    std_dev
+                )
+            }
+            DistributionError::EmptyRange(lo, hi) => {
+                write!(f, "range is empty, {:?} is not smaller than {:?}", lo, hi)
+            }
+        }
+    }
+}
+
+impl Error for DistributionError {}
+
+/// Converts seconds into a [Duration], saturating at [`Duration::MAX`].
+fn secs_to_duration(secs: f64) -> Duration {
+    Duration::try_from_secs_f64(secs).unwrap_or(Duration::MAX)
+}
+
+/// Exponential distribution of inter-arrival times with mean `1 / rate` seconds.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Exponential {
+    rate: f64,
+}
+
+impl Exponential {
+    /// Create an exponential distribution with `rate` events per second.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DistributionError::InvalidRate`] if `rate` is not finite or not greater than zero.
+    pub fn new(rate: f64) -> Result<Self, DistributionError> {
+        if rate.is_finite() && rate > 0.0 {
+            Ok(Self { rate })
+        } else {
+            Err(DistributionError::InvalidRate(rate))
+        }
+    }
+
+    #[must_use]
+    pub fn rate(&self) -> f64 {
+        self.rate
+    }
+
+    /// Draw an inter-arrival time.
+    pub fn sample(&self, rng: &mut SimRng) -> Duration {
+        // 1 - uniform is in (0, 1] so the logarithm is always finite.
+        let secs = -(1.0 - rng.uniform()).ln() / self.rate;
+        secs_to_duration(secs)
+    }
+}
+
+/// Normal (Gaussian) distribution.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Normal {
+    mean: f64,
+    std_dev: f64,
+}
+
+impl Normal {
+    /// # Errors
+    ///
+    /// Returns [`DistributionError::InvalidMean`] if `mean` is not finite or
+    /// [`DistributionError::InvalidStdDev`] if `std_dev` is negative or not finite.
+    pub fn new(mean: f64, std_dev: f64) -> Result<Self, DistributionError> {
+        if !mean.is_finite() {
+            return Err(DistributionError::InvalidMean(mean));
+        }
+        if !std_dev.is_finite() || std_dev < 0.0 {
+            return Err(DistributionError::InvalidStdDev(std_dev));
+        }
+        Ok(Self { mean, std_dev })
+    }
+
+    #[must_use]
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    #[must_use]
+    pub fn std_dev(&self) -> f64 {
+        self.std_dev
+    }
+
+    /// Draw a value using the Box–Muller transform.
+    pub fn sample(&self, rng: &mut SimRng) -> f64 {
+        // 1 - uniform is in (0, 1] so the logarithm is always finite.
+        let radius = (-2.0 * (1.0 - rng.uniform()).ln()).sqrt();
+        let angle = TAU * rng.uniform();
+        self.mean + self.std_dev * radius * angle.cos()
+    }
+
+    /// Draw a value as a number of seconds, negative values are truncated to zero.
+    pub fn sample_duration(&self, rng: &mut SimRng) -> Duration {
+        secs_to_duration(self.sample(rng).max(0.0))
+    }
+}
+
+/// Uniform distribution of durations in `[lo, hi)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UniformDuration {
+    lo: Duration,
+    hi: Duration,
+}
+
+impl UniformDuration {
+    /// # Errors
+    ///
+    /// Returns [`DistributionError::EmptyRange`] if `lo` is not smaller than `hi`.
+    pub fn new(lo: Duration, hi: Duration) -> Result<Self, DistributionError> {
+        if lo < hi {
+            Ok(Self { lo, hi })
+        } else {
+            Err(DistributionError::EmptyRange(lo, hi))
+        }
+    }
+
+    #[must_use]
+    pub fn lo(&self) -> Duration {
+        self.lo
+    }
+
+    #[must_use]
+    pub fn hi(&self) -> Duration {
+        self.hi
+    }
+
+    /// Draw a duration in `[lo, hi)` with nanosecond resolution.
+    pub fn sample(&self, rng: &mut SimRng) -> Duration {
+        let span = (self.hi - self.lo).as_nanos();
+        // Rounding of the product can reach `span` for very large ranges.
+        let offset = ((span as f64 * rng.uniform()) as u128).min(span - 1);
+        let secs = (offset / NANOS_PER_SEC) as u64;
+        let nanos = (offset % NANOS_PER_SEC) as u32;
+        self.lo + Duration::new(secs, nanos)
+    }
+}
+
+const NANOS_PER_SEC: u128 = 1_000_000_000;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn exponential_rejects_invalid_rates() {
+        assert_eq!(
+            Err(DistributionError::InvalidRate(0.0)),
+            Exponential::new(0.0)
+        );
+        assert_eq!(
+            Err(DistributionError::InvalidRate(-1.0)),
+            Exponential::new(-1.0)
+        );
+        assert!(Exponential::new(f64::NAN).is_err());
+        assert!(Exponential::new(f64::INFINITY).is_err());
+        assert!(Exponential::new(0.5).is_ok());
+    }
+
+    #[test]
+    fn exponential_sample_mean() {
+        let rate = 4.0;
+        let exponential = Exponential::new(rate).unwrap();
+        let mut rng = SimRng::new(1);
+        let samples = 100_000;
+        let total: f64 = (0..samples)
+            .map(|_| exponential.sample(&mut rng).as_secs_f64())
+            .sum();
+        let mean = total / samples as f64;
+        let expected = 1.0 / rate;
+        assert!(
+            (mean - expected).abs() < expected * 0.02,
+            "mean = {}, expected = {}",
+            mean,
+            expected
+        );
+    }
+
+    #[test]
+    fn normal_rejects_invalid_parameters() {
+        assert_eq!(
+            Err(DistributionError::InvalidStdDev(-1.0)),
+            Normal::new(0.0, -1.0)
+        );
+        assert!(Normal::new(f64::NAN, 1.0).is_err());
+        assert!(Normal::new(0.0, f64::INFINITY).is_err());
+        assert!(Normal::new(-3.0, 0.0).is_ok());
+    }
+
+    #[test]
+    fn normal_sample_mean_and_std_dev() {
+        let normal = Normal::new(10.0, 2.0).unwrap();
+        let mut rng = SimRng::new(2);
+        let samples: Vec<f64> = (0..100_000).map(|_| normal.sample(&mut rng)).collect();
+        let n = samples.len() as f64;
+        let mean = samples.iter().sum::<f64>() / n;
+        let variance = samples.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (n - 1.0);
+        assert!((mean - 10.0).abs() < 0.05, "mean = {}", mean);
+        assert!(
+            (variance.sqrt() - 2.0).abs() < 0.05,
+            "std_dev = {}",
+            variance.sqrt()
+        );
+    }
+
+    #[test]
+    fn normal_sample_duration_is_truncated() {
+        let normal = Normal::new(-5.0, 1.0).unwrap();
+        let mut rng = SimRng::new(3);
+        for _ in 0..100 {
+            assert_eq!(Duration::ZERO, normal.sample_duration(&mut rng));
+        }
+    }
+
+    #[test]
+    fn uniform_duration_rejects_empty_ranges() {
+        let second = Duration::from_secs(1);
+        assert_eq!(
+            Err(DistributionError::EmptyRange(second, second)),
+            UniformDuration::new(second, second)
+        );
+        assert!(UniformDuration::new(second * 2, second).is_err());
+    }
+
+    #[test]
+    fn uniform_duration_samples_in_range() {
+        let lo = Duration::from_secs(2);
+        let hi = Duration::from_secs(6);
+        let uniform = UniformDuration::new(lo, hi).unwrap();
+        let mut rng = SimRng::new(4);
+        let samples = 100_000;
+        let mut total = Duration::ZERO;
+        for _ in 0..samples {
+            let sample = uniform.sample(&mut rng);
+            assert!(lo <= sample && sample < hi, "{:?} out of range", sample);
+            total += sample;
+        }
+        let mean = total.as_secs_f64() / samples as f64;
+        assert!((mean - 4.0).abs() < 0.02, "mean = {}", mean);
+    }
+}
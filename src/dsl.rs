@@ -0,0 +1,252 @@
+//! Fluent description of a model that compiles into a [Simulation].
+//!
+//! ```
+//! # #![feature(coroutines)]
+//! use std::time::Duration;
+//! use rustsim::{dsl, Action};
+//!
+//! let mut simulation = dsl::model()
+//!     .component("server", |_| {
+//!         Box::new(#[coroutine] |_| {
+//!             yield Action::Hold(Duration::from_secs(1));
+//!         })
+//!     })
+//!     .source("arrivals", |model| {
+//!         let server = model.key("server");
+//!         Box::new(#[coroutine] move |_| {
+//!             yield Action::Hold(Duration::from_secs(1));
+//!             yield Action::ActivateOne(server);
+//!         })
+//!     })
+//!     .at(Duration::from_secs(2))
+//!     .build();
+//! simulation.run_until_empty();
+//! assert_eq!(Duration::from_secs(4), simulation.time());
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::{ClockRef, EntityState, GenBoxed, Key, Simulation};
+
+type Factory = Box<dyn FnOnce(&ModelContext) -> GenBoxed<()>>;
+
+struct Definition {
+    name: String,
+    factory: Factory,
+    start: Option<Duration>,
+}
+
+/// Start describing a model.
+#[must_use]
+pub fn model() -> Model {
+    Model::default()
+}
+
+/// Components of a model and the times they start at, see [`model`].
+#[derive(Default)]
+pub struct Model {
+    definitions: Vec<Definition>,
+}
+
+impl fmt::Debug for Model {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let components: Vec<_> = self
+            .definitions
+            .iter()
+            .map(|definition| (&definition.name, definition.start))
+            .collect();
+        f.debug_struct("Model")
+            .field("components", &components)
+            .finish()
+    }
+}
+
+impl Model {
+    /// Add a component named `name` that starts passive, its generator runs from the
+    /// beginning when another component activates it.
+    ///
+    /// `factory` builds its generator when the model is [built](Model::build) and can
+    /// look up the key of any component of the model, declared before or after it.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the model already has a component named `name`.
+    #[must_use]
+    pub fn component(
+        mut self,
+        name: impl Into<String>,
+        factory: impl FnOnce(&ModelContext) -> GenBoxed<()> + 'static,
+    ) -> Self {
+        let name = name.into();
+        assert!(
+            self.definitions
+                .iter()
+                .all(|definition| definition.name != name),
+            "The model already has a component named {:?}",
+            name
+        );
+        self.definitions.push(Definition {
+            name,
+            factory: Box::new(factory),
+            start: None,
+        });
+        self
+    }
+
+    /// Add a component named `name` that starts at time zero, unless moved with [`Model::at`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the model already has a component named `name`.
+    #[must_use]
+    pub fn source(
+        self,
+        name: impl Into<String>,
+        factory: impl FnOnce(&ModelContext) -> GenBoxed<()> + 'static,
+    ) -> Self {
+        self.component(name, factory).at(Duration::ZERO)
+    }
+
+    /// Start the last added component at `time`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if no component was added yet.
+    #[must_use]
+    pub fn at(mut self, time: Duration) -> Self {
+        let definition = self
+            .definitions
+            .last_mut()
+            .expect("A component must be added before setting its start time");
+        definition.start = Some(time);
+        self
+    }
+
+    /// Build a simulation with the generators of every component, in the order they were
+    /// added, and schedule the ones with a start time. The others are passive.
+    #[must_use]
+    pub fn build(self) -> Simulation<()> {
+        let mut simulation = Simulation::default();
+        // Keys are handed out in insertion order so they are known before the generators.
+        let context = ModelContext {
+            keys: self
+                .definitions
+                .iter()
+                .enumerate()
+                .map(|(id, definition)| (definition.name.clone(), Key::new(id)))
+                .collect(),
+            clock: simulation.clock(),
+        };
+        for definition in self.definitions {
+            let gen = (definition.factory)(&context);
+            let key = match definition.start {
+                Some(start) => {
+                    let key = simulation.add_generator(gen);
+                    simulation.schedule(start, key);
+                    key
+                }
+                None => simulation.reattach(gen, EntityState::Passive),
+            };
+            debug_assert_eq!(context.keys[&definition.name], key);
+        }
+        simulation
+    }
+}
+
+/// What the factories of a [Model] can see of the simulation being built.
+#[derive(Debug)]
+pub struct ModelContext {
+    keys: BTreeMap<String, Key>,
+    clock: ClockRef,
+}
+
+impl ModelContext {
+    /// Returns the key of the component named `name`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the model has no component named `name`.
+    #[must_use]
+    pub fn key(&self, name: &str) -> Key {
+        *self
+            .keys
+            .get(name)
+            .unwrap_or_else(|| panic!("The model has no component named {:?}", name))
+    }
+
+    /// Returns the clock of the simulation being built.
+    #[must_use]
+    pub fn clock(&self) -> ClockRef {
+        self.clock.clone()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::Cell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::distributions::Exponential;
+    use crate::rng::SimRng;
+    use crate::Action;
+
+    #[test]
+    fn mm1_queue_from_the_dsl() {
+        let queue = Rc::new(Cell::new(0u32));
+        let server_idle = Rc::new(Cell::new(true));
+        let served = Rc::new(Cell::new(0u32));
+
+        let (arrival_queue, arrival_idle) = (Rc::clone(&queue), Rc::clone(&server_idle));
+        let (server_queue, server_flag, server_served) = (
+            Rc::clone(&queue),
+            Rc::clone(&server_idle),
+            Rc::clone(&served),
+        );
+        let mut simulation = model()
+            .source("arrivals", move |model| {
+                let server = model.key("server");
+                let inter_arrival = Exponential::new(0.8).unwrap();
+                let mut rng = SimRng::new(1);
+                Box::new(
+                    #[coroutine]
+                    move |_| loop {
+                        yield Action::Hold(inter_arrival.sample(&mut rng));
+                        arrival_queue.set(arrival_queue.get() + 1);
+                        if arrival_idle.replace(false) {
+                            yield Action::ActivateOne(server);
+                        }
+                    },
+                )
+            })
+            .component("server", move |_| {
+                let service = Exponential::new(1.0).unwrap();
+                let mut rng = SimRng::new(2);
+                Box::new(
+                    #[coroutine]
+                    move |_| loop {
+                        while server_queue.get() > 0 {
+                            server_queue.set(server_queue.get() - 1);
+                            yield Action::Hold(service.sample(&mut rng));
+                            server_served.set(server_served.get() + 1);
+                        }
+                        server_flag.set(true);
+                        yield Action::Passivate;
+                    },
+                )
+            })
+            .build();
+
+        let server = Key::new(1);
+        assert_eq!(vec![(Duration::ZERO, Key::new(0))], simulation.dump_queue());
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(server));
+        simulation.run_until_predicate(|| (), |_| served.get() == 50);
+
+        assert_eq!(50, served.get());
+        // Roughly 50 services at rate 1 with the server busy 80% of the time.
+        let time = simulation.time().as_secs_f64();
+        assert!(time > 30.0 && time < 120.0, "time = {}", time);
+    }
+}
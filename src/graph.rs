@@ -0,0 +1,51 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::keys::Key;
+
+/// Directed graph of the activations observed during a simulation.
+///
+/// An edge `from -> to` is recorded every time the entity `from` activates `to`.
+#[derive(Debug, Default, Clone)]
+pub struct ActivationGraph {
+    edges: BTreeMap<(Key, Key), usize>,
+}
+
+impl ActivationGraph {
+    pub fn record(&mut self, from: Key, to: Key) {
+        *self.edges.entry((from, to)).or_default() += 1;
+    }
+
+    /// Returns how many times `from` activated `to`.
+    #[must_use]
+    pub fn count(&self, from: Key, to: Key) -> usize {
+        self.edges.get(&(from, to)).copied().unwrap_or_default()
+    }
+
+    /// Returns the keys that took part in at least one activation.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.edges.keys().flat_map(|&(from, to)| [from, to])
+    }
+
+    /// Render the graph in GraphViz DOT format.
+    ///
+    /// `nodes` are always included even if they never took part in an activation.
+    /// Edges observed more than once are labeled with their count.
+    #[must_use]
+    pub fn to_dot(&self, nodes: impl IntoIterator<Item = Key>) -> String {
+        let mut dot = String::from("digraph activations {\n");
+        for node in nodes {
+            writeln!(dot, "    {0} [label=\"{0}\"];", node.id).unwrap();
+        }
+        for (&(from, to), &count) in &self.edges {
+            if count > 1 {
+                writeln!(dot, "    {} -> {} [label=\"{}\"];", from.id, to.id, count).unwrap();
+            } else {
+                writeln!(dot, "    {} -> {};", from.id, to.id).unwrap();
+            }
+        }
+        dot.push('}');
+        dot.push('\n');
+        dot
+    }
+}
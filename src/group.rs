@@ -0,0 +1,25 @@
+use crate::{Key, KeySet};
+
+/// Identifies a [Group] created with [`Simulation::create_group`](crate::Simulation::create_group).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupId(pub(crate) usize);
+
+/// A set of entities activated and passivated together, like the machines of a shift.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Group {
+    members: KeySet,
+}
+
+impl Group {
+    pub(crate) fn new(members: impl IntoIterator<Item = Key>) -> Self {
+        Self {
+            members: members.into_iter().collect(),
+        }
+    }
+
+    /// Returns the keys of the entities in the group.
+    #[must_use]
+    pub fn members(&self) -> &KeySet {
+        &self.members
+    }
+}
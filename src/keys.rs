@@ -1,4 +1,12 @@
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Key {
     pub(crate) id: usize,
 }
@@ -21,6 +29,201 @@ impl Key {
     }
 }
 
+impl fmt::Display for Key {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{}", self.id)
+    }
+}
+
+/// A [Key] tagged with a marker type for the kind of entity it belongs to.
+///
+/// Keys of different kinds can't be mixed up, functions taking a `TypedKey<Server>`
+/// reject a `TypedKey<Customer>` at compile time. At runtime it is just a [Key].
+///
+/// ```compile_fail
+/// #![feature(coroutines)]
+/// use rustsim::{action, GenBoxed, Simulation};
+///
+/// struct Server;
+/// struct Customer;
+///
+/// fn idle() -> GenBoxed<()> {
+///     Box::new(#[coroutine] |_| {
+///         yield action::passivate();
+///     })
+/// }
+///
+/// let mut simulation = Simulation::default();
+/// let server = simulation.add_generator_typed::<Server>(idle());
+/// let customer = simulation.add_generator_typed::<Customer>(idle());
+/// // Servers and customers can't be activated together.
+/// let _ = action::activate_many(&[server, customer]);
+/// ```
+pub struct TypedKey<K> {
+    key: Key,
+    marker: PhantomData<fn() -> K>,
+}
+
+impl<K> TypedKey<K> {
+    pub(crate) fn new(key: Key) -> Self {
+        Self {
+            key,
+            marker: PhantomData,
+        }
+    }
+
+    /// Returns the untyped [Key].
+    #[must_use]
+    pub fn key(self) -> Key {
+        self.key
+    }
+}
+
+impl<K> From<TypedKey<K>> for Key {
+    fn from(typed: TypedKey<K>) -> Self {
+        typed.key
+    }
+}
+
+// Implemented by hand so `K` doesn't need to implement the traits.
+impl<K> Clone for TypedKey<K> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<K> Copy for TypedKey<K> {}
+
+impl<K> PartialEq for TypedKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        self.key == other.key
+    }
+}
+
+impl<K> Eq for TypedKey<K> {}
+
+impl<K> Hash for TypedKey<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.key.hash(state);
+    }
+}
+
+impl<K> fmt::Debug for TypedKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("TypedKey").field(&self.key).finish()
+    }
+}
+
+impl<K> fmt::Display for TypedKey<K> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.key.fmt(f)
+    }
+}
+
+/// Ordered set of keys without duplicates, for building [`Action::ActivateMany`](crate::Action::ActivateMany) lists.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeySet {
+    keys: BTreeSet<Key>,
+}
+
+impl KeySet {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `key` to the set, returns `false` if it was already present.
+    pub fn insert(&mut self, key: Key) -> bool {
+        self.keys.insert(key)
+    }
+
+    /// Remove `key` from the set, returns `false` if it wasn't present.
+    pub fn remove(&mut self, key: Key) -> bool {
+        self.keys.remove(&key)
+    }
+
+    #[must_use]
+    pub fn contains(&self, key: Key) -> bool {
+        self.keys.contains(&key)
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.keys.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Returns an iterator over the keys in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = Key> + '_ {
+        self.keys.iter().copied()
+    }
+
+    /// Returns the keys in ascending order, ready for an `ActivateMany`.
+    #[must_use]
+    pub fn to_vec(&self) -> Vec<Key> {
+        self.iter().collect()
+    }
+}
+
+impl FromIterator<Key> for KeySet {
+    fn from_iter<I: IntoIterator<Item = Key>>(iter: I) -> Self {
+        Self {
+            keys: iter.into_iter().collect(),
+        }
+    }
+}
+
+impl Extend<Key> for KeySet {
+    fn extend<I: IntoIterator<Item = Key>>(&mut self, iter: I) {
+        self.keys.extend(iter);
+    }
+}
+
+/// Shared pool of reusable buffers for [`Action::ActivateMany`](crate::Action::ActivateMany).
+///
+/// Take a buffer with [`KeyBufferPool::take`], fill it and yield it in an `ActivateMany`,
+/// the simulation gives it back to the pool once the activations are applied.
+#[derive(Debug, Clone, Default)]
+pub struct KeyBufferPool {
+    buffers: Rc<RefCell<Vec<Vec<Key>>>>,
+}
+
+impl KeyBufferPool {
+    /// Maximum number of buffers kept around, any extra buffer is dropped.
+    const MAX_POOLED: usize = 16;
+
+    /// Takes an empty buffer from the pool or creates a new one if the pool is empty.
+    #[must_use]
+    pub fn take(&self) -> Vec<Key> {
+        self.buffers.borrow_mut().pop().unwrap_or_default()
+    }
+
+    /// Clears `buffer` and returns it to the pool.
+    pub fn give_back(&self, mut buffer: Vec<Key>) {
+        let mut buffers = self.buffers.borrow_mut();
+        if buffer.capacity() > 0 && buffers.len() < Self::MAX_POOLED {
+            buffer.clear();
+            buffers.push(buffer);
+        }
+    }
+
+    /// Returns the number of buffers available in the pool.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.buffers.borrow().len()
+    }
+
+    /// Returns `true` if the pool has no buffers available.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.buffers.borrow().is_empty()
+    }
+}
+
 // #[derive(Debug)]
 // pub struct StateKey<T> {
 //     pub(crate) id: usize,
@@ -56,3 +259,16 @@ impl Key {
 //         Self { id, value }
 //     }
 // }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn key_formatting() {
+        let key = Key::new(7);
+        assert_eq!("#7", key.to_string());
+        assert_eq!("Key { id: 7 }", format!("{:?}", key));
+        assert_eq!("#1 -> #2", format!("{} -> {}", Key::new(1), Key::new(2)));
+    }
+}
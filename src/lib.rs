@@ -1,17 +1,45 @@
 #![feature(coroutines, coroutine_trait)]
+#![cfg_attr(test, feature(stmt_expr_attributes))]
 // use std::cell::Cell;
 
+pub mod action;
 mod container;
+pub mod distributions;
+pub mod dsl;
+mod graph;
+mod group;
 mod keys;
+mod pacing;
+pub mod processes;
+mod replay;
+pub mod replications;
+pub mod resources;
+pub mod rng;
+pub mod scenario;
 mod scheduler;
 mod simulation;
 mod state;
+pub mod stats;
+#[cfg(feature = "async")]
+mod stream;
+mod timeline;
 
 use std::{ops::Coroutine, time::Duration};
 
-pub use keys::Key;
-pub use simulation::{Simulation, ShouldContinue};
+pub use container::{EntityState, StateCounts, WakeReason};
+pub use graph::ActivationGraph;
+pub use group::{Group, GroupId};
+pub use keys::{Key, KeyBufferPool, KeySet, TypedKey};
+pub use pacing::{MockClock, SystemClock, WallClock};
+pub use replay::{ReplayMismatch, ReplayRecorder};
+pub use scheduler::{ClockRef, QueueId, TieBreak};
+pub use simulation::{
+    PastEventError, RecurringId, RunOutcome, ShouldContinue, Simulation, StepError, StepInfo,
+};
 pub use state::{State, StateKey};
+#[cfg(feature = "async")]
+pub use stream::StepStream;
+pub use timeline::StateTimeline;
 
 pub type GenBoxed<R, C = ()> = Box<dyn Coroutine<R, Yield = Action, Return = C> + Unpin>;
 
@@ -20,10 +48,39 @@ pub type GenBoxed<R, C = ()> = Box<dyn Coroutine<R, Yield = Action, Return = C>
 #[derive(Debug, Clone)]
 pub enum Action {
     Hold(Duration),
+    /// Hold for the first duration but resume no later than the absolute time given by the
+    /// second, for example the end of a shift. The generator can tell it was cut short
+    /// by comparing the clock with the time it meant to finish at. A cap in the past
+    /// resumes it now.
+    HoldCapped(Duration, Duration),
     Passivate,
     ActivateOne(Key),
+    /// Activate every listed entity.
+    ///
+    /// With the default [TieBreak::Fifo] the caller is resumed first and the targets
+    /// follow in the order listed, after the events already scheduled for the same time.
     ActivateMany(Vec<Key>),
     Cancel(Key),
+    /// Same as `ActivateMany` for up to four targets, without allocating. `None` entries
+    /// are skipped.
+    ActivateSmall([Option<Key>; 4]),
+    /// Schedule the target after the duration without changing its state, the caller is
+    /// resumed now. As with [`Simulation::schedule`] a target that already has a pending
+    /// event keeps it instead. A passive target becomes active when the event resumes it,
+    /// activating it earlier moves the event to the activation time.
+    ScheduleOther(Key, Duration),
+    /// Activate the targets like `ActivateMany` and resume the caller after the hold,
+    /// instead of resuming it now.
+    ActivateManyThenHold(Vec<Key>, Duration),
+    /// Apply several actions in one yield.
+    ///
+    /// The actions on other entities are applied in order, then the one deciding when the
+    /// caller resumes, a `Hold`, `HoldCapped`, `Passivate` or `ActivateManyThenHold`, if there
+    /// is one.
+    /// Without one the caller is resumed now, as after an `ActivateOne`. A nested `Multi`
+    /// or more than one of those is a [StepError::InvalidMulti]. If an inner action fails
+    /// the ones before it stay applied.
+    Multi(Vec<Action>),
 }
 
 impl Action {
@@ -35,6 +92,40 @@ impl Action {
     pub fn activate_many(keys: Vec<Key>) -> Self {
         Action::ActivateMany(keys)
     }
+
+    /// Returns the variant of the action without its data.
+    #[must_use]
+    pub fn kind(&self) -> ActionKind {
+        match self {
+            Action::Hold(_) => ActionKind::Hold,
+            Action::HoldCapped(..) => ActionKind::HoldCapped,
+            Action::Passivate => ActionKind::Passivate,
+            Action::ActivateOne(_) => ActionKind::ActivateOne,
+            Action::ActivateMany(_) => ActionKind::ActivateMany,
+            Action::Cancel(_) => ActionKind::Cancel,
+            Action::ActivateSmall(_) => ActionKind::ActivateSmall,
+            Action::ScheduleOther(..) => ActionKind::ScheduleOther,
+            Action::ActivateManyThenHold(..) => ActionKind::ActivateManyThenHold,
+            Action::Multi(_) => ActionKind::Multi,
+        }
+    }
+}
+
+/// What a generator did when it was resumed, see [`Simulation::step_with_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ActionKind {
+    Hold,
+    HoldCapped,
+    Passivate,
+    ActivateOne,
+    ActivateMany,
+    Cancel,
+    ActivateSmall,
+    ScheduleOther,
+    ActivateManyThenHold,
+    Multi,
+    /// The generator returned instead of yielding an action.
+    Completed,
 }
 
 // thread_local! {
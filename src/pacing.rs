@@ -0,0 +1,176 @@
+use std::time::{Duration, Instant};
+
+/// Source of wall-clock time for [`Simulation::run_real_time_with`](crate::Simulation::run_real_time_with).
+pub trait WallClock {
+    /// Returns the time elapsed since an arbitrary starting point, it must never decrease.
+    fn now(&mut self) -> Duration;
+
+    /// Wait for `duration`.
+    fn sleep(&mut self, duration: Duration);
+}
+
+/// The process clock, sleeping blocks the thread.
+#[derive(Debug, Clone, Copy)]
+pub struct SystemClock {
+    start: Instant,
+}
+
+impl Default for SystemClock {
+    fn default() -> Self {
+        Self {
+            start: Instant::now(),
+        }
+    }
+}
+
+impl SystemClock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl WallClock for SystemClock {
+    fn now(&mut self) -> Duration {
+        self.start.elapsed()
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        std::thread::sleep(duration);
+    }
+}
+
+/// A virtual clock that only moves when told to, sleeping advances it right away.
+///
+/// Makes real-time runs deterministic and instant, for tests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MockClock {
+    now: Duration,
+    sleeps: Vec<Duration>,
+}
+
+impl MockClock {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Move the clock forward by `duration`, like time spent computing.
+    pub fn advance(&mut self, duration: Duration) {
+        self.now += duration;
+    }
+
+    /// Returns every duration slept so far, in order.
+    #[must_use]
+    pub fn sleeps(&self) -> &[Duration] {
+        &self.sleeps
+    }
+}
+
+impl WallClock for MockClock {
+    fn now(&mut self) -> Duration {
+        self.now
+    }
+
+    fn sleep(&mut self, duration: Duration) {
+        self.sleeps.push(duration);
+        self.advance(duration);
+    }
+}
+
+/// Computes how long to wait so simulation time doesn't advance faster than wall-clock time.
+///
+/// Each event is paced relative to the previous one, time spent computing counts
+/// towards the wait and a late event is processed right away.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Pacer {
+    last_wall: Duration,
+    last_sim: Duration,
+    /// Simulated seconds per wall-clock second.
+    speed_factor: f64,
+}
+
+impl Pacer {
+    pub(crate) fn new(wall: Duration, sim: Duration, speed_factor: f64) -> Self {
+        Self {
+            last_wall: wall,
+            last_sim: sim,
+            speed_factor,
+        }
+    }
+
+    /// Returns how long to sleep at `now` before processing an event at `next`.
+    pub(crate) fn delay(&self, now: Duration, next: Duration) -> Duration {
+        let delta = next
+            .saturating_sub(self.last_sim)
+            .div_f64(self.speed_factor);
+        let target = self.last_wall + delta;
+        target.saturating_sub(now)
+    }
+
+    /// Record that the event at `sim` was processed at `wall`.
+    pub(crate) fn mark(&mut self, wall: Duration, sim: Duration) {
+        self.last_wall = wall;
+        self.last_sim = sim;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn delay_waits_for_the_simulated_delta() {
+        let start = Duration::from_secs(100);
+        let secs = Duration::from_secs;
+        let mut pacer = Pacer::new(start, secs(10), 1.0);
+
+        assert_eq!(secs(3), pacer.delay(start, secs(13)));
+        // Part of the delta was already spent computing.
+        assert_eq!(secs(2), pacer.delay(start + secs(1), secs(13)));
+        assert_eq!(Duration::ZERO, pacer.delay(start, secs(10)));
+
+        pacer.mark(start + secs(3), secs(13));
+        assert_eq!(secs(1), pacer.delay(start + secs(3), secs(14)));
+    }
+
+    #[test]
+    fn late_events_dont_wait() {
+        let start = Duration::from_secs(100);
+        let pacer = Pacer::new(start, Duration::ZERO, 1.0);
+        let late = start + Duration::from_secs(5);
+        assert_eq!(Duration::ZERO, pacer.delay(late, Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn speed_factor_scales_the_delay() {
+        let start = Duration::from_secs(100);
+        let delta = Duration::from_secs(4);
+        let delay =
+            |speed_factor| Pacer::new(start, Duration::ZERO, speed_factor).delay(start, delta);
+
+        assert_eq!(Duration::from_secs(4), delay(1.0));
+        assert_eq!(Duration::from_secs(2), delay(2.0));
+        assert_eq!(Duration::from_secs(1), delay(4.0));
+        assert_eq!(Duration::from_secs(8), delay(0.5));
+    }
+
+    #[test]
+    fn mock_clock_paces_without_sleeping() {
+        let secs = Duration::from_secs;
+        let mut clock = MockClock::new();
+        let mut pacer = Pacer::new(clock.now(), Duration::ZERO, 2.0);
+
+        // Half a second of the four simulated ones is spent computing.
+        clock.advance(Duration::from_millis(500));
+        let delay = pacer.delay(clock.now(), secs(4));
+        assert_eq!(Duration::from_millis(1500), delay);
+        clock.sleep(delay);
+        pacer.mark(clock.now(), secs(4));
+
+        clock.advance(secs(3));
+        assert_eq!(Duration::ZERO, pacer.delay(clock.now(), secs(6)));
+        assert_eq!(&[Duration::from_millis(1500)], clock.sleeps());
+        assert_eq!(secs(5), clock.now());
+    }
+}
@@ -0,0 +1,72 @@
+use std::time::Duration;
+
+use crate::distributions::Exponential;
+use crate::rng::SimRng;
+use crate::scheduler::ClockRef;
+use crate::{Action, GenBoxed};
+
+/// A generator that produces arrivals following a Poisson process with `rate` arrivals per second.
+///
+/// It repeatedly holds for an exponential inter-arrival time drawn from `rng`
+/// and then calls `on_arrival` with the current simulation time read from `clock`.
+/// The generator never completes.
+///
+/// # Panics
+///
+/// Panics if `rate` is not finite or not greater than zero.
+pub fn poisson_arrivals(
+    rate: f64,
+    mut rng: SimRng,
+    clock: ClockRef,
+    mut on_arrival: impl FnMut(Duration) + 'static,
+) -> GenBoxed<()> {
+    let inter_arrival = Exponential::new(rate).unwrap_or_else(|err| panic!("{}", err));
+    Box::new(
+        #[coroutine]
+        move |_| loop {
+            yield Action::Hold(inter_arrival.sample(&mut rng));
+            on_arrival(clock.time());
+        },
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::Simulation;
+
+    #[test]
+    fn poisson_arrivals_are_reproducible() {
+        let run = |seed: u64| {
+            let arrivals = Rc::new(RefCell::new(Vec::new()));
+            let mut simulation = Simulation::default();
+            let recorder = Rc::clone(&arrivals);
+            let key = simulation.add_generator(poisson_arrivals(
+                2.0,
+                SimRng::new(seed),
+                simulation.clock(),
+                move |now| recorder.borrow_mut().push(now),
+            ));
+            simulation.schedule_now(key);
+            simulation.run_with_limit(Duration::from_secs(10));
+            arrivals.take()
+        };
+
+        let arrivals = run(11);
+        assert_eq!(arrivals, run(11));
+        assert_ne!(arrivals, run(12));
+
+        // The arrival times are the running sum of the inter-arrival samples.
+        let exponential = Exponential::new(2.0).unwrap();
+        let mut rng = SimRng::new(11);
+        let mut now = Duration::ZERO;
+        for &arrival in &arrivals {
+            now += exponential.sample(&mut rng);
+            assert_eq!(now, arrival);
+        }
+        assert!(arrivals.len() > 5);
+    }
+}
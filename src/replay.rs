@@ -0,0 +1,87 @@
+use std::error::Error;
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::keys::Key;
+
+/// Trace of the events processed by a simulation, in the order they were processed.
+///
+/// Enabled with [`Simulation::record_replay`](crate::Simulation::record_replay).
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ReplayRecorder {
+    events: Vec<(Duration, Key)>,
+}
+
+impl ReplayRecorder {
+    pub(crate) fn record(&mut self, time: Duration, key: Key) {
+        self.events.push((time, key));
+    }
+
+    /// Returns the processed events as `(time, key)` pairs.
+    #[must_use]
+    pub fn events(&self) -> &[(Duration, Key)] {
+        &self.events
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Write the trace as JSON to `writer`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if writing fails.
+    #[cfg(feature = "serde")]
+    pub fn save(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error> {
+        serde_json::to_writer(writer, self)
+    }
+
+    /// Read a trace previously written with [`ReplayRecorder::save`].
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if reading fails or the data isn't a valid trace.
+    #[cfg(feature = "serde")]
+    pub fn load(reader: impl std::io::Read) -> Result<Self, serde_json::Error> {
+        serde_json::from_reader(reader)
+    }
+}
+
+/// First difference found by [`Simulation::run_with_replay_check`](crate::Simulation::run_with_replay_check).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayMismatch {
+    /// Position of the event in the trace.
+    pub index: usize,
+    /// The recorded event.
+    pub expected: (Duration, Key),
+    /// The event the simulation processed instead, `None` if it ran out of events.
+    pub actual: Option<(Duration, Key)>,
+}
+
+impl fmt::Display for ReplayMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (time, key) = self.expected;
+        write!(
+            f,
+            "event {} should be {} at {:?} but ",
+            self.index, key, time
+        )?;
+        match self.actual {
+            Some((time, key)) => write!(f, "was {} at {:?}", key, time),
+            None => write!(f, "there were no events left"),
+        }
+    }
+}
+
+impl Error for ReplayMismatch {}
@@ -0,0 +1,90 @@
+//! Running independent replications of a model in parallel.
+
+use std::num::NonZeroUsize;
+use std::thread;
+
+use crate::Simulation;
+
+/// Run `n` replications of a model and collect one output per replication, in order.
+///
+/// Replication `i` builds its own simulation with `factory(i as u64)`, using the argument
+/// as the seed of its random numbers (see [`SeedSequence`](crate::rng::SeedSequence) to
+/// derive unrelated seeds from a master seed), runs it until no events are left and hands
+/// it to `extract`. Simulations aren't shared between replications so they are independent
+/// and can run on different threads, one per available core.
+pub fn run_replications<O, F, X>(n: usize, factory: F, extract: X) -> Vec<O>
+where
+    O: Send,
+    F: Fn(u64) -> Simulation<()> + Sync,
+    X: Fn(&mut Simulation<()>) -> O + Sync,
+{
+    let threads = thread::available_parallelism()
+        .map_or(1, NonZeroUsize::get)
+        .min(n);
+    let run = |replication: usize| {
+        let mut simulation = factory(replication as u64);
+        simulation.run_until_empty();
+        extract(&mut simulation)
+    };
+    let mut outputs: Vec<(usize, O)> = thread::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|thread| {
+                let run = &run;
+                scope.spawn(move || {
+                    (thread..n)
+                        .step_by(threads)
+                        .map(|replication| (replication, run(replication)))
+                        .collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().expect("a replication panicked"))
+            .collect()
+    });
+    outputs.sort_unstable_by_key(|&(replication, _)| replication);
+    outputs.into_iter().map(|(_, output)| output).collect()
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::distributions::Exponential;
+    use crate::rng::SimRng;
+    use crate::Action;
+
+    fn model(seed: u64) -> Simulation<()> {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let mut rng = SimRng::new(seed);
+                let exponential = Exponential::new(1.0).unwrap();
+                for _ in 0..10 {
+                    yield Action::Hold(exponential.sample(&mut rng));
+                }
+            },
+        ));
+        simulation.schedule_now(key);
+        simulation
+    }
+
+    #[test]
+    fn replications_are_independent() {
+        let end_time = |simulation: &mut Simulation<()>| simulation.time();
+        let outputs = run_replications(8, model, end_time);
+
+        assert_eq!(8, outputs.len());
+        for (seed, &output) in outputs.iter().enumerate() {
+            let mut simulation = model(seed as u64);
+            simulation.run_until_empty();
+            assert_eq!(simulation.time(), output);
+        }
+        assert!(outputs.windows(2).all(|pair| pair[0] != pair[1]));
+        assert!(outputs.iter().all(|&output| output > Duration::ZERO));
+        assert!(run_replications(0, model, end_time).is_empty());
+    }
+}
@@ -0,0 +1,1142 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BTreeSet, BinaryHeap, VecDeque};
+use std::time::Duration;
+
+use crate::keys::Key;
+use crate::rng::SimRng;
+use crate::scheduler::ClockRef;
+use crate::stats::Accumulate;
+
+/// Result of [`Store::get`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Get<T> {
+    /// An item was taken from the store.
+    ///
+    /// If `wake` is set a blocked producer had its item moved into the store
+    /// and must be activated with [`Action::ActivateOne`](crate::Action::ActivateOne).
+    Item { item: T, wake: Option<Key> },
+    /// The store is empty, the consumer was queued and must passivate.
+    ///
+    /// Once activated the consumer calls [`Store::get`] again to receive the
+    /// item a producer reserved for it.
+    Wait,
+}
+
+/// Result of [`Store::put`].
+#[derive(Debug, PartialEq, Eq)]
+pub enum Put {
+    /// The item was accepted.
+    ///
+    /// If `wake` is set the item was reserved for a waiting consumer which
+    /// must be activated with [`Action::ActivateOne`](crate::Action::ActivateOne).
+    Stored { wake: Option<Key> },
+    /// The store is full, the producer was queued and must passivate.
+    ///
+    /// The item is moved into the store by the consumer that activates the producer,
+    /// so there is nothing left to do after resuming.
+    Wait,
+}
+
+/// A store of items shared between producers and consumers.
+///
+/// Producers [put](Store::put) items and consumers [get](Store::get) them in FIFO order.
+/// When the store is empty consumers wait and when it is full (if it has a capacity)
+/// producers wait. Waiting is done by passivating, whoever unblocks a waiting entity
+/// is told its [Key] so it can yield an activation for it.
+#[derive(Debug)]
+pub struct Store<T> {
+    items: VecDeque<T>,
+    capacity: Option<usize>,
+    consumers: VecDeque<Key>,
+    producers: VecDeque<(Key, T)>,
+    reserved: Vec<(Key, T)>,
+}
+
+impl<T> Default for Store<T> {
+    fn default() -> Self {
+        Self {
+            items: VecDeque::new(),
+            capacity: None,
+            consumers: VecDeque::new(),
+            producers: VecDeque::new(),
+            reserved: Vec::new(),
+        }
+    }
+}
+
+impl<T> Store<T> {
+    /// Creates a store without a capacity limit, producers never wait.
+    #[must_use]
+    pub fn unbounded() -> Self {
+        Self::default()
+    }
+
+    /// Creates a store holding at most `capacity` items.
+    #[must_use]
+    pub fn bounded(capacity: usize) -> Self {
+        Self {
+            capacity: Some(capacity),
+            ..Self::default()
+        }
+    }
+
+    /// Take the next item for the consumer `key`.
+    pub fn get(&mut self, key: Key) -> Get<T> {
+        if let Some(index) = self.reserved.iter().position(|&(owner, _)| owner == key) {
+            let (_, item) = self.reserved.swap_remove(index);
+            return Get::Item { item, wake: None };
+        }
+        match self.items.pop_front() {
+            Some(item) => {
+                let wake = self.producers.pop_front().map(|(producer, pending)| {
+                    self.items.push_back(pending);
+                    producer
+                });
+                Get::Item { item, wake }
+            }
+            None => {
+                self.consumers.push_back(key);
+                Get::Wait
+            }
+        }
+    }
+
+    /// Add `item` to the store on behalf of the producer `key`.
+    pub fn put(&mut self, key: Key, item: T) -> Put {
+        if let Some(consumer) = self.consumers.pop_front() {
+            self.reserved.push((consumer, item));
+            Put::Stored {
+                wake: Some(consumer),
+            }
+        } else if self.is_full() {
+            self.producers.push_back((key, item));
+            Put::Wait
+        } else {
+            self.items.push_back(item);
+            Put::Stored { wake: None }
+        }
+    }
+
+    /// Returns the number of items available in the store.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    #[must_use]
+    pub fn is_full(&self) -> bool {
+        self.capacity
+            .is_some_and(|capacity| self.items.len() >= capacity)
+    }
+
+    /// Returns the keys of the consumers waiting for an item.
+    pub fn waiting_consumers(&self) -> impl Iterator<Item = Key> + '_ {
+        self.consumers.iter().copied()
+    }
+
+    /// Returns the keys of the producers waiting for space in the store.
+    pub fn waiting_producers(&self) -> impl Iterator<Item = Key> + '_ {
+        self.producers.iter().map(|&(key, _)| key)
+    }
+}
+
+/// Typed messages queued per receiving entity.
+///
+/// Senders [send](Mailbox::send) messages to the key of the receiver, which takes them
+/// in FIFO order with [`Mailbox::recv`]. A receiver with an empty mailbox passivates and
+/// the next sender is told to activate it, from outside the generators
+/// [`Simulation::send`](crate::Simulation::send) does both.
+#[derive(Debug)]
+pub struct Mailbox<T> {
+    messages: BTreeMap<Key, VecDeque<T>>,
+    waiting: BTreeSet<Key>,
+}
+
+impl<T> Default for Mailbox<T> {
+    fn default() -> Self {
+        Self {
+            messages: BTreeMap::new(),
+            waiting: BTreeSet::new(),
+        }
+    }
+}
+
+impl<T> Mailbox<T> {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue `message` for the receiver `key`.
+    ///
+    /// Returns the receiver's key if it was waiting for a message, the sender must
+    /// activate it with [`Action::ActivateOne`](crate::Action::ActivateOne).
+    pub fn send(&mut self, key: Key, message: T) -> Option<Key> {
+        self.messages.entry(key).or_default().push_back(message);
+        self.waiting.remove(&key).then_some(key)
+    }
+
+    /// Take the oldest message of the receiver `key`.
+    ///
+    /// Returns `None` if there is none, the receiver is then waiting and must passivate.
+    pub fn recv(&mut self, key: Key) -> Option<T> {
+        let message = self.messages.get_mut(&key).and_then(VecDeque::pop_front);
+        if message.is_none() {
+            self.waiting.insert(key);
+        }
+        message
+    }
+
+    /// Returns the number of messages queued for `key`.
+    #[must_use]
+    pub fn len(&self, key: Key) -> usize {
+        self.messages.get(&key).map_or(0, VecDeque::len)
+    }
+
+    /// Returns `true` if no message is queued for `key`.
+    #[must_use]
+    pub fn is_empty(&self, key: Key) -> bool {
+        self.len(key) == 0
+    }
+
+    /// Returns `true` if `key` found its mailbox empty and wasn't sent a message since.
+    #[must_use]
+    pub fn is_waiting(&self, key: Key) -> bool {
+        self.waiting.contains(&key)
+    }
+}
+
+/// Result of requesting a unit of a [Resource].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Request {
+    /// A unit was granted, the requester can use it right away.
+    Granted,
+    /// No unit is free, the requester was queued and must passivate.
+    ///
+    /// The unit is granted before the requester is activated again.
+    Wait,
+}
+
+/// Order in which a [Resource] serves the entities waiting for a unit.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum QueueDiscipline {
+    /// First come, first served.
+    #[default]
+    Fifo,
+    /// Last come, first served.
+    Lifo,
+    /// Lowest priority number first, FIFO among equal priorities.
+    Priority,
+    /// In random order, reproducible with the same seed.
+    Random(u64),
+}
+
+#[derive(Debug)]
+enum WaitQueue {
+    Fifo(VecDeque<Key>),
+    Lifo(Vec<Key>),
+    Priority {
+        heap: BinaryHeap<Reverse<(u32, u64, Key)>>,
+        next_sequence: u64,
+    },
+    /// Kept in serving order, each key is inserted at a random position.
+    Random {
+        queue: VecDeque<Key>,
+        rng: SimRng,
+    },
+}
+
+impl WaitQueue {
+    fn new(discipline: QueueDiscipline) -> Self {
+        match discipline {
+            QueueDiscipline::Fifo => WaitQueue::Fifo(VecDeque::new()),
+            QueueDiscipline::Lifo => WaitQueue::Lifo(Vec::new()),
+            QueueDiscipline::Priority => WaitQueue::Priority {
+                heap: BinaryHeap::new(),
+                next_sequence: 0,
+            },
+            QueueDiscipline::Random(seed) => WaitQueue::Random {
+                queue: VecDeque::new(),
+                rng: SimRng::new(seed),
+            },
+        }
+    }
+
+    fn push(&mut self, key: Key, priority: u32) {
+        match self {
+            WaitQueue::Fifo(queue) => queue.push_back(key),
+            WaitQueue::Lifo(stack) => stack.push(key),
+            WaitQueue::Priority {
+                heap,
+                next_sequence,
+            } => {
+                heap.push(Reverse((priority, *next_sequence, key)));
+                *next_sequence += 1;
+            }
+            WaitQueue::Random { queue, rng } => {
+                let index = rng.below(queue.len() + 1);
+                queue.insert(index, key);
+            }
+        }
+    }
+
+    fn pop(&mut self) -> Option<Key> {
+        match self {
+            WaitQueue::Fifo(queue) => queue.pop_front(),
+            WaitQueue::Lifo(stack) => stack.pop(),
+            WaitQueue::Priority { heap, .. } => heap.pop().map(|Reverse((_, _, key))| key),
+            WaitQueue::Random { queue, .. } => queue.pop_front(),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            WaitQueue::Fifo(queue) => queue.len(),
+            WaitQueue::Lifo(stack) => stack.len(),
+            WaitQueue::Priority { heap, .. } => heap.len(),
+            WaitQueue::Random { queue, .. } => queue.len(),
+        }
+    }
+
+    /// Returns the waiting keys in the order they will be served.
+    fn keys(&self) -> Vec<Key> {
+        match self {
+            WaitQueue::Fifo(queue) | WaitQueue::Random { queue, .. } => {
+                queue.iter().copied().collect()
+            }
+            WaitQueue::Lifo(stack) => stack.iter().rev().copied().collect(),
+            WaitQueue::Priority { heap, .. } => {
+                let mut entries: Vec<_> = heap.iter().map(|Reverse(entry)| *entry).collect();
+                entries.sort_unstable();
+                entries.into_iter().map(|(_, _, key)| key).collect()
+            }
+        }
+    }
+}
+
+/// A resource with a fixed number of units.
+///
+/// Entities [request](Resource::request) a unit and [release](Resource::release) it when done.
+/// When every unit is in use requesters wait and are served following the
+/// resource's [QueueDiscipline], FIFO by default.
+///
+/// The clock is used to keep track of how long the units are in use, see [`Resource::utilization`].
+#[derive(Debug)]
+pub struct Resource {
+    capacity: usize,
+    users: Vec<Key>,
+    waiting: WaitQueue,
+    in_use_stats: Accumulate,
+    queue_stats: Accumulate,
+}
+
+impl Resource {
+    #[must_use]
+    pub fn new(capacity: usize, clock: ClockRef) -> Self {
+        Self::with_discipline(capacity, QueueDiscipline::default(), clock)
+    }
+
+    #[must_use]
+    pub fn with_discipline(capacity: usize, discipline: QueueDiscipline, clock: ClockRef) -> Self {
+        Self {
+            capacity,
+            users: Vec::with_capacity(capacity),
+            waiting: WaitQueue::new(discipline),
+            in_use_stats: Accumulate::new(clock.clone(), 0.0),
+            queue_stats: Accumulate::new(clock, 0.0),
+        }
+    }
+
+    /// Request a unit on behalf of `key`.
+    ///
+    /// With [`QueueDiscipline::Priority`] the request has priority 0, the highest.
+    pub fn request(&mut self, key: Key) -> Request {
+        self.request_with_priority(key, 0)
+    }
+
+    /// Request a unit on behalf of `key` with the given `priority`, lower numbers are served first.
+    ///
+    /// The priority is ignored unless the resource uses [`QueueDiscipline::Priority`].
+    pub fn request_with_priority(&mut self, key: Key, priority: u32) -> Request {
+        if self.users.len() < self.capacity {
+            self.users.push(key);
+            self.in_use_stats.update(self.users.len() as f64);
+            Request::Granted
+        } else {
+            self.waiting.push(key, priority);
+            self.queue_stats.update(self.waiting.len() as f64);
+            Request::Wait
+        }
+    }
+
+    /// Release the unit held by `key`.
+    ///
+    /// If an entity was waiting the unit is granted to it and its key is returned,
+    /// the caller must activate it with [`Action::ActivateOne`](crate::Action::ActivateOne).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` doesn't hold a unit of the resource.
+    pub fn release(&mut self, key: Key) -> Option<Key> {
+        let index = self
+            .users
+            .iter()
+            .position(|&user| user == key)
+            .unwrap_or_else(|| panic!("Entity ID = {} released a unit it didn't hold", key.id));
+        self.users.swap_remove(index);
+        let next = self.waiting.pop();
+        if let Some(next) = next {
+            self.users.push(next);
+            self.queue_stats.update(self.waiting.len() as f64);
+        }
+        self.in_use_stats.update(self.users.len() as f64);
+        next
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of units in use.
+    #[must_use]
+    pub fn in_use(&self) -> usize {
+        self.users.len()
+    }
+
+    /// Returns the number of entities waiting for a unit.
+    #[must_use]
+    pub fn queue_len(&self) -> usize {
+        self.waiting.len()
+    }
+
+    /// Returns the keys of the entities waiting for a unit in the order they will be served.
+    #[must_use]
+    pub fn waiting(&self) -> Vec<Key> {
+        self.waiting.keys()
+    }
+
+    /// Returns the time-average number of units in use divided by the capacity,
+    /// since the resource was created.
+    #[must_use]
+    pub fn utilization(&self) -> f64 {
+        if self.capacity == 0 {
+            return 0.0;
+        }
+        self.in_use_stats.mean() / self.capacity as f64
+    }
+
+    /// Returns the time every unit would have been in use to do the same work,
+    /// the time the resource was busy when the capacity is one.
+    #[must_use]
+    pub fn busy_time(&self) -> Duration {
+        self.in_use_stats.elapsed().mul_f64(self.utilization())
+    }
+
+    /// Returns the time-average number of entities waiting for a unit since the resource was created.
+    #[must_use]
+    pub fn avg_queue_len(&self) -> f64 {
+        self.queue_stats.mean()
+    }
+
+    /// Returns the largest number of entities that waited for a unit at the same time.
+    #[must_use]
+    pub fn max_queue_len(&self) -> usize {
+        self.queue_stats.max() as usize
+    }
+
+    /// Returns the time elapsed since the resource was created minus the [busy time](Resource::busy_time).
+    #[must_use]
+    pub fn idle_time(&self) -> Duration {
+        self.in_use_stats.elapsed().saturating_sub(self.busy_time())
+    }
+}
+
+/// Result of requesting a unit of a [PreemptiveResource].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PreemptiveRequest {
+    /// A unit was granted.
+    ///
+    /// If `preempted` is set the unit was taken from that entity, the requester
+    /// must interrupt it by yielding [`Action::Cancel`](crate::Action::Cancel).
+    Granted { preempted: Option<Key> },
+    /// No unit could be granted, the requester was queued and must passivate.
+    Wait,
+}
+
+/// Information given to an entity that lost its unit to a higher priority request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Preemption {
+    /// How long the entity held the unit before being preempted.
+    pub served: Duration,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Holder {
+    key: Key,
+    priority: u32,
+    since: Duration,
+}
+
+/// A [Resource] where requests with a higher priority take units away from lower priority holders.
+///
+/// Lower numbers mean higher priority. A preempted holder goes back to the queue and, once it's
+/// granted a unit again and activated, it can learn how much service it received with
+/// [`PreemptiveResource::take_preemption`]. Waiters are served by priority, FIFO among equals.
+#[derive(Debug)]
+pub struct PreemptiveResource {
+    capacity: usize,
+    clock: ClockRef,
+    holders: Vec<Holder>,
+    waiting: Vec<(u32, u64, Key)>,
+    preempted: Vec<(Key, Preemption)>,
+    next_sequence: u64,
+}
+
+impl PreemptiveResource {
+    #[must_use]
+    pub fn new(capacity: usize, clock: ClockRef) -> Self {
+        Self {
+            capacity,
+            clock,
+            holders: Vec::with_capacity(capacity),
+            waiting: Vec::new(),
+            preempted: Vec::new(),
+            next_sequence: 0,
+        }
+    }
+
+    /// Request a unit on behalf of `key` with the given `priority`.
+    pub fn request(&mut self, key: Key, priority: u32) -> PreemptiveRequest {
+        let now = self.clock.time();
+        if self.holders.len() < self.capacity {
+            self.grant(key, priority);
+            return PreemptiveRequest::Granted { preempted: None };
+        }
+        let lowest = self
+            .holders
+            .iter()
+            .enumerate()
+            .max_by_key(|(_, holder)| holder.priority)
+            .filter(|(_, holder)| holder.priority > priority)
+            .map(|(index, _)| index);
+        match lowest {
+            Some(index) => {
+                let evicted = self.holders.swap_remove(index);
+                self.preempted.push((
+                    evicted.key,
+                    Preemption {
+                        served: now.saturating_sub(evicted.since),
+                    },
+                ));
+                self.enqueue(evicted.key, evicted.priority);
+                self.grant(key, priority);
+                PreemptiveRequest::Granted {
+                    preempted: Some(evicted.key),
+                }
+            }
+            None => {
+                self.enqueue(key, priority);
+                PreemptiveRequest::Wait
+            }
+        }
+    }
+
+    /// Release the unit held by `key`.
+    ///
+    /// Returns the key of the highest priority waiter, now holding the unit, which
+    /// the caller must activate with [`Action::ActivateOne`](crate::Action::ActivateOne).
+    ///
+    /// # Panics
+    ///
+    /// Panics if `key` doesn't hold a unit of the resource.
+    pub fn release(&mut self, key: Key) -> Option<Key> {
+        let index = self
+            .holders
+            .iter()
+            .position(|holder| holder.key == key)
+            .unwrap_or_else(|| panic!("Entity ID = {} released a unit it didn't hold", key.id));
+        self.holders.swap_remove(index);
+        let (index, _) = self
+            .waiting
+            .iter()
+            .enumerate()
+            .min_by_key(|&(_, &(priority, sequence, _))| (priority, sequence))?;
+        let (priority, _, next) = self.waiting.remove(index);
+        self.grant(next, priority);
+        Some(next)
+    }
+
+    /// Returns the last preemption suffered by `key`, if any, and forgets it.
+    pub fn take_preemption(&mut self, key: Key) -> Option<Preemption> {
+        let index = self.preempted.iter().position(|&(owner, _)| owner == key)?;
+        Some(self.preempted.swap_remove(index).1)
+    }
+
+    #[must_use]
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    /// Returns the number of units in use.
+    #[must_use]
+    pub fn in_use(&self) -> usize {
+        self.holders.len()
+    }
+
+    fn grant(&mut self, key: Key, priority: u32) {
+        self.holders.push(Holder {
+            key,
+            priority,
+            since: self.clock.time(),
+        });
+    }
+
+    fn enqueue(&mut self, key: Key, priority: u32) {
+        self.waiting.push((priority, self.next_sequence, key));
+        self.next_sequence += 1;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::distributions::Exponential;
+    use crate::rng::SimRng;
+    use crate::stats::SojournRecorder;
+    use crate::{Action, EntityState, Simulation};
+
+    #[test]
+    fn bounded_store_blocks_both_ways() {
+        let store = Rc::new(RefCell::new(Store::bounded(1)));
+        let consumed = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let clock = simulation.clock();
+
+        let consumer_store = Rc::clone(&store);
+        let consumer_log = Rc::clone(&consumed);
+        let consumer = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let me = Key::new(0);
+                for _ in 0..3 {
+                    let item = loop {
+                        let outcome = consumer_store.borrow_mut().get(me);
+                        match outcome {
+                            Get::Item { item, wake } => {
+                                if let Some(producer) = wake {
+                                    yield Action::ActivateOne(producer);
+                                }
+                                break item;
+                            }
+                            Get::Wait => yield Action::Passivate,
+                        }
+                    };
+                    consumer_log.borrow_mut().push((clock.time(), item));
+                    yield Action::Hold(Duration::from_secs(2));
+                }
+            },
+        ));
+        assert_eq!(Key::new(0), consumer);
+
+        let producer_store = Rc::clone(&store);
+        let producer = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let me = Key::new(1);
+                yield Action::Hold(Duration::from_secs(1));
+                for item in 1..=3 {
+                    let outcome = producer_store.borrow_mut().put(me, item);
+                    match outcome {
+                        Put::Stored {
+                            wake: Some(consumer),
+                        } => {
+                            yield Action::ActivateOne(consumer);
+                        }
+                        Put::Stored { wake: None } => {}
+                        Put::Wait => yield Action::Passivate,
+                    }
+                }
+            },
+        ));
+
+        simulation.schedule_now(consumer);
+        simulation.schedule_now(producer);
+        simulation.step_with(());
+        simulation.step_with(());
+        // The consumer found the store empty, the producer is holding.
+        assert_eq!(
+            vec![consumer],
+            store.borrow().waiting_consumers().collect::<Vec<_>>()
+        );
+
+        simulation.run_until_empty();
+
+        let seconds = Duration::from_secs;
+        assert_eq!(
+            vec![(seconds(1), 1), (seconds(3), 2), (seconds(5), 3)],
+            *consumed.borrow()
+        );
+        let store = store.borrow();
+        assert!(store.is_empty());
+        assert_eq!(0, store.waiting_producers().count());
+        assert_eq!(0, store.waiting_consumers().count());
+        // The producer completed after being unblocked by the consumer.
+        assert_eq!(
+            Some(EntityState::Completed),
+            simulation.entity_state(producer)
+        );
+    }
+
+    #[test]
+    fn consumer_waiting_on_a_finished_producer_is_stuck() {
+        let store = Rc::new(RefCell::new(Store::unbounded()));
+        let mut simulation = Simulation::default();
+
+        let consumer_store = Rc::clone(&store);
+        let consumer = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| loop {
+                let me = Key::new(0);
+                let item = consumer_store.borrow_mut().get(me);
+                if item == Get::Wait {
+                    yield Action::Passivate;
+                }
+            },
+        ));
+        let producer_store = Rc::clone(&store);
+        let producer = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                // Only one item for a consumer that always wants more.
+                yield Action::Hold(Duration::from_secs(1));
+                let wake = match producer_store.borrow_mut().put(Key::new(1), 'a') {
+                    Put::Stored { wake } => wake,
+                    Put::Wait => None,
+                };
+                if let Some(wake) = wake {
+                    yield Action::ActivateOne(wake);
+                }
+            },
+        ));
+        simulation.schedule_now(consumer);
+        simulation.schedule_now(producer);
+        assert!(simulation.stuck_components().is_empty());
+        simulation.run_until_empty();
+
+        assert_eq!(vec![consumer], simulation.stuck_components());
+        assert_eq!(
+            Some(EntityState::Completed),
+            simulation.entity_state(producer)
+        );
+    }
+
+    #[test]
+    fn full_store_queues_producer() {
+        let mut store = Store::bounded(1);
+        let (producer, consumer) = (Key::new(0), Key::new(1));
+        assert_eq!(Put::Stored { wake: None }, store.put(producer, 'a'));
+        assert!(store.is_full());
+        assert_eq!(Put::Wait, store.put(producer, 'b'));
+        assert_eq!(
+            Get::Item {
+                item: 'a',
+                wake: Some(producer)
+            },
+            store.get(consumer)
+        );
+        // The blocked item was moved into the store.
+        assert_eq!(1, store.len());
+        assert_eq!(
+            Get::Item {
+                item: 'b',
+                wake: None
+            },
+            store.get(consumer)
+        );
+        assert_eq!(Get::Wait, store.get(consumer));
+    }
+
+    #[test]
+    fn mailbox_delivers_messages_in_order() {
+        let mailbox = Rc::new(RefCell::new(Mailbox::new()));
+        let received = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+
+        let (consumer_mailbox, log) = (Rc::clone(&mailbox), Rc::clone(&received));
+        let consumer = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| loop {
+                let me = Key::new(0);
+                let message = consumer_mailbox.borrow_mut().recv(me);
+                match message {
+                    Some(message) => log.borrow_mut().push(message),
+                    None => yield Action::Passivate,
+                }
+            },
+        ));
+        for (name, hold) in [("a", 1), ("b", 2)] {
+            let mailbox = Rc::clone(&mailbox);
+            let sender = simulation.add_generator(Box::new(
+                #[coroutine]
+                move |_| {
+                    for n in 1..=2 {
+                        yield Action::Hold(Duration::from_secs(hold));
+                        let wake = mailbox
+                            .borrow_mut()
+                            .send(consumer, format!("{}{}", name, n));
+                        if let Some(wake) = wake {
+                            yield Action::ActivateOne(wake);
+                        }
+                    }
+                },
+            ));
+            simulation.schedule_now(sender);
+        }
+        simulation.schedule_now(consumer);
+        simulation.run_until_empty();
+        assert!(mailbox.borrow().is_waiting(consumer));
+
+        simulation.send(&mut mailbox.borrow_mut(), consumer, "c".to_owned());
+        simulation.run_until_empty();
+
+        // "b1" and "a2" are both sent at 2s, the hold of "b1" was scheduled first.
+        assert_eq!(vec!["a1", "b1", "a2", "b2", "c"], *received.borrow());
+        assert!(mailbox.borrow().is_empty(consumer));
+        assert_eq!(
+            Some(EntityState::Passive),
+            simulation.entity_state(consumer)
+        );
+    }
+
+    #[test]
+    fn resource_serves_waiters_in_order() {
+        let mut resource = Resource::new(1, Simulation::<()>::default().clock());
+        let (a, b, c) = (Key::new(0), Key::new(1), Key::new(2));
+        assert_eq!(Request::Granted, resource.request(a));
+        assert_eq!(Request::Wait, resource.request(b));
+        assert_eq!(Request::Wait, resource.request(c));
+        assert_eq!(Some(b), resource.release(a));
+        assert_eq!(Some(c), resource.release(b));
+        assert_eq!(None, resource.release(c));
+        assert_eq!(0, resource.in_use());
+    }
+
+    #[test]
+    fn resource_queue_disciplines() {
+        let serve_order = |discipline| {
+            let mut resource =
+                Resource::with_discipline(1, discipline, Simulation::<()>::default().clock());
+            let holder = Key::new(0);
+            assert_eq!(Request::Granted, resource.request(holder));
+            for (id, priority) in [(1, 3), (2, 1), (3, 2), (4, 1)] {
+                let outcome = resource.request_with_priority(Key::new(id), priority);
+                assert_eq!(Request::Wait, outcome);
+            }
+            let queued = resource.waiting();
+            let mut served = Vec::new();
+            let mut current = holder;
+            while let Some(next) = resource.release(current) {
+                served.push(next.id());
+                current = next;
+            }
+            assert_eq!(
+                queued.iter().map(|key| key.id()).collect::<Vec<_>>(),
+                served
+            );
+            served
+        };
+
+        assert_eq!(vec![1, 2, 3, 4], serve_order(QueueDiscipline::Fifo));
+        assert_eq!(vec![4, 3, 2, 1], serve_order(QueueDiscipline::Lifo));
+        assert_eq!(vec![2, 4, 3, 1], serve_order(QueueDiscipline::Priority));
+        let random = serve_order(QueueDiscipline::Random(5));
+        assert_eq!(random, serve_order(QueueDiscipline::Random(5)));
+        let mut sorted = random.clone();
+        sorted.sort_unstable();
+        assert_eq!(vec![1, 2, 3, 4], sorted);
+    }
+
+    #[test]
+    fn higher_priority_preempts_holder() {
+        let mut simulation = Simulation::default();
+        let resource = Rc::new(RefCell::new(PreemptiveResource::new(1, simulation.clock())));
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let clock = simulation.clock();
+
+        // Needs 10 seconds of service with priority 5.
+        let low_resource = Rc::clone(&resource);
+        let low_log = Rc::clone(&log);
+        let low_clock = simulation.clock();
+        let low = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let me = Key::new(0);
+                let mut remaining = Duration::from_secs(10);
+                let outcome = low_resource.borrow_mut().request(me, 5);
+                assert_eq!(PreemptiveRequest::Granted { preempted: None }, outcome);
+                loop {
+                    yield Action::Hold(remaining);
+                    let preemption = low_resource.borrow_mut().take_preemption(me);
+                    match preemption {
+                        Some(preemption) => {
+                            low_log
+                                .borrow_mut()
+                                .push(("low preempted", preemption.served));
+                            remaining -= preemption.served;
+                        }
+                        None => break,
+                    }
+                }
+                low_log.borrow_mut().push(("low done", low_clock.time()));
+                let next = low_resource.borrow_mut().release(me);
+                assert_eq!(None, next);
+            },
+        ));
+
+        // Arrives at 3 seconds with priority 1 and needs 4 seconds of service.
+        let high_resource = Rc::clone(&resource);
+        let high_log = Rc::clone(&log);
+        let high = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let me = Key::new(1);
+                yield Action::Hold(Duration::from_secs(3));
+                let outcome = high_resource.borrow_mut().request(me, 1);
+                if let PreemptiveRequest::Granted {
+                    preempted: Some(holder),
+                } = outcome
+                {
+                    yield Action::Cancel(holder);
+                }
+                yield Action::Hold(Duration::from_secs(4));
+                high_log.borrow_mut().push(("high done", clock.time()));
+                let next = high_resource.borrow_mut().release(me);
+                if let Some(next) = next {
+                    yield Action::ActivateOne(next);
+                }
+            },
+        ));
+        assert_eq!((Key::new(0), Key::new(1)), (low, high));
+
+        simulation.schedule_now(low);
+        simulation.schedule_now(high);
+        simulation.run_until_empty();
+
+        let seconds = Duration::from_secs;
+        assert_eq!(
+            vec![
+                ("high done", seconds(7)),
+                ("low preempted", seconds(3)),
+                ("low done", seconds(14)),
+            ],
+            *log.borrow()
+        );
+    }
+
+    #[test]
+    fn resource_utilization() {
+        let mut simulation = Simulation::default();
+        let resource = Rc::new(RefCell::new(Resource::new(1, simulation.clock())));
+        let user_resource = Rc::clone(&resource);
+        let user = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let me = Key::new(0);
+                for (busy, idle) in [(3, 1), (2, 2)] {
+                    assert_eq!(Request::Granted, user_resource.borrow_mut().request(me));
+                    yield Action::Hold(Duration::from_secs(busy));
+                    user_resource.borrow_mut().release(me);
+                    yield Action::Hold(Duration::from_secs(idle));
+                }
+            },
+        ));
+        simulation.schedule_now(user);
+        simulation.run_until_empty();
+
+        // Busy for 5 of the 8 seconds.
+        let resource = resource.borrow();
+        assert_eq!(Duration::from_secs(8), simulation.time());
+        assert!((resource.utilization() - 5.0 / 8.0).abs() < 1e-9);
+        assert_eq!(Duration::from_secs(5), resource.busy_time());
+        assert_eq!(Duration::from_secs(3), resource.idle_time());
+    }
+
+    #[test]
+    fn generators_request_with_their_own_key() {
+        let mut simulation = Simulation::default();
+        let resource = Rc::new(RefCell::new(Resource::new(1, simulation.clock())));
+        let mut users = Vec::new();
+        for _ in 0..2 {
+            let resource = Rc::clone(&resource);
+            let user = simulation.add_generator_with_key(|me| {
+                Box::new(
+                    #[coroutine]
+                    move |_| {
+                        yield Action::Hold(Duration::from_secs(1));
+                        if resource.borrow_mut().request(me) == Request::Wait {
+                            yield Action::Passivate;
+                        }
+                        yield Action::Hold(Duration::from_secs(2));
+                        let next = resource.borrow_mut().release(me);
+                        if let Some(next) = next {
+                            yield Action::ActivateOne(next);
+                        }
+                    },
+                )
+            });
+            simulation.schedule_now(user);
+            users.push(user);
+        }
+        // Reaped slots are reused and the factory sees the reused key.
+        simulation.run_until_empty();
+        assert_eq!(2, simulation.reap_completed());
+        let reused = simulation.add_generator_with_key(|me| {
+            let resource = Rc::clone(&resource);
+            Box::new(
+                #[coroutine]
+                move |_| {
+                    assert_eq!(Request::Granted, resource.borrow_mut().request(me));
+                    yield Action::Hold(Duration::from_secs(1));
+                    resource.borrow_mut().release(me);
+                },
+            )
+        });
+        assert!(users.contains(&reused));
+        simulation.schedule_now(reused);
+        simulation.run_until_empty();
+
+        assert_eq!(Duration::from_secs(6), simulation.time());
+        assert_eq!(0, resource.borrow().in_use());
+    }
+
+    #[test]
+    fn resource_queue_length_statistics() {
+        let mut simulation = Simulation::default();
+        let resource = Rc::new(RefCell::new(Resource::new(1, simulation.clock())));
+        for id in 0..3 {
+            let resource = Rc::clone(&resource);
+            let key = simulation.add_generator(Box::new(
+                #[coroutine]
+                move |_| {
+                    let me = Key::new(id);
+                    if resource.borrow_mut().request(me) == Request::Wait {
+                        yield Action::Passivate;
+                    }
+                    yield Action::Hold(Duration::from_secs(2));
+                    let next = resource.borrow_mut().release(me);
+                    if let Some(next) = next {
+                        yield Action::ActivateOne(next);
+                    }
+                },
+            ));
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+
+        // Two waiting during [0, 2), one during [2, 4) and none during [4, 6).
+        let resource = resource.borrow();
+        assert_eq!(Duration::from_secs(6), simulation.time());
+        assert!((resource.avg_queue_len() - 1.0).abs() < 1e-9);
+        assert_eq!(2, resource.max_queue_len());
+        assert!((resource.utilization() - 1.0).abs() < 1e-9);
+    }
+
+    /// Run an M/M/1 queue with arrival rate `lambda` and service rate `mu` for `run_length`
+    /// seconds, returning the server and the sojourn times of the customers that left.
+    fn mm1(lambda: f64, mu: f64, run_length: f64, seed: u64) -> (Resource, SojournRecorder) {
+        let mut simulation = Simulation::default();
+        let server = Rc::new(RefCell::new(Resource::new(1, simulation.clock())));
+        let inter_arrival = Exponential::new(lambda).unwrap();
+        let service = Exponential::new(mu).unwrap();
+        let mut rng = SimRng::new(seed);
+        let mut sojourns = SojournRecorder::new();
+        let run_length = Duration::from_secs_f64(run_length);
+        let mut arrivals = Vec::new();
+        let mut arrival = inter_arrival.sample(&mut rng);
+        while arrival < run_length {
+            arrivals.push(arrival);
+            arrival += inter_arrival.sample(&mut rng);
+        }
+
+        // The source activates the passive customers, keys 1 to n, at their arrival times.
+        let clock = simulation.clock();
+        let source_arrivals = arrivals.clone();
+        let source = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                for (id, arrival) in source_arrivals.into_iter().enumerate() {
+                    yield Action::Hold(arrival - clock.time());
+                    yield Action::ActivateOne(Key::new(id + 1));
+                }
+            },
+        ));
+        simulation.schedule_now(source);
+        for (id, arrival) in arrivals.into_iter().enumerate() {
+            let server = Rc::clone(&server);
+            let service_time = service.sample(&mut rng);
+            let customer = simulation.reattach(
+                Box::new(
+                    #[coroutine]
+                    move |_| {
+                        let me = Key::new(id + 1);
+                        if server.borrow_mut().request(me) == Request::Wait {
+                            yield Action::Passivate;
+                        }
+                        yield Action::Hold(service_time);
+                        let next = server.borrow_mut().release(me);
+                        if let Some(next) = next {
+                            yield Action::ActivateOne(next);
+                        }
+                    },
+                ),
+                EntityState::Passive,
+            );
+            sojourns.enter(customer, arrival);
+        }
+        simulation.record_sojourns(sojourns);
+        simulation.advance_to(run_length);
+
+        let sojourns = simulation.sojourns().unwrap().clone();
+        drop(simulation);
+        let server = Rc::try_unwrap(server).unwrap().into_inner();
+        (server, sojourns)
+    }
+
+    #[test]
+    fn mm1_utilization_converges_to_rho() {
+        let (lambda, mu) = (0.5, 1.0);
+        let (server, sojourns) = mm1(lambda, mu, 50_000.0, 7);
+
+        let rho = lambda / mu;
+        let utilization = server.utilization();
+        assert!(
+            (utilization - rho).abs() < 0.02,
+            "utilization = {}",
+            utilization
+        );
+        // Little's law for the queue: Lq = rho^2 / (1 - rho).
+        let queue = server.avg_queue_len();
+        assert!(
+            (queue - rho * rho / (1.0 - rho)).abs() < 0.1,
+            "queue = {}",
+            queue
+        );
+        // Mean time in system: W = 1 / (mu - lambda).
+        let sojourn = sojourns.summary().mean();
+        assert!(
+            (sojourn - 1.0 / (mu - lambda)).abs() < 0.2,
+            "sojourn = {}",
+            sojourn
+        );
+    }
+}
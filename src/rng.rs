@@ -0,0 +1,230 @@
+/// Seedable pseudo random number generator for simulations.
+///
+/// Implements xoshiro256** with the state initialized from the seed using SplitMix64,
+/// the same seed always produces the same stream of numbers.
+/// Not suitable for cryptographic purposes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SimRng {
+    state: [u64; 4],
+}
+
+/// Advance a SplitMix64 state and return the next output.
+pub(crate) fn splitmix64(state: &mut u64) -> u64 {
+    *state = state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+/// Derives reproducible seeds for independent replications from a master seed.
+///
+/// The seed of replication `k` is the `k`-th output of a SplitMix64 stream started at
+/// the master seed, so it only depends on the master seed and `k`, never on the order
+/// in which replications run, and different replications always get different seeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SeedSequence {
+    master_seed: u64,
+}
+
+impl SeedSequence {
+    #[must_use]
+    pub fn new(master_seed: u64) -> Self {
+        Self { master_seed }
+    }
+
+    /// Returns the seed of the given replication.
+    #[must_use]
+    pub fn seed_for(&self, replication: usize) -> u64 {
+        let mut state = self
+            .master_seed
+            .wrapping_add((replication as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        splitmix64(&mut state)
+    }
+}
+
+impl SimRng {
+    #[must_use]
+    pub fn new(seed: u64) -> Self {
+        let mut seed = seed;
+        let state = [
+            splitmix64(&mut seed),
+            splitmix64(&mut seed),
+            splitmix64(&mut seed),
+            splitmix64(&mut seed),
+        ];
+        Self { state }
+    }
+
+    /// Returns the next random `u64`.
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+
+        result
+    }
+
+    /// Returns a random `f64` uniformly distributed in `[0, 1)`.
+    pub fn uniform(&mut self) -> f64 {
+        // The 53 most significant bits fill the mantissa of the f64.
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+
+    /// Returns a random index in `0..bound`, `bound` must not be zero.
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        // Lemire's multiply-shift, the bias is negligible for the bounds used in models.
+        ((u128::from(self.next_u64()) * bound as u128) >> 64) as usize
+    }
+
+    /// Returns a random element of `slice`, `None` if it is empty.
+    pub fn choose<'a, T>(&mut self, slice: &'a [T]) -> Option<&'a T> {
+        if slice.is_empty() {
+            return None;
+        }
+        slice.get(self.below(slice.len()))
+    }
+
+    /// Returns an index of `weights` chosen with probability proportional to its weight.
+    ///
+    /// # Panics
+    ///
+    /// Panics if a weight is negative or not finite or if they are all zero.
+    pub fn weighted_choice(&mut self, weights: &[f64]) -> usize {
+        assert!(
+            weights
+                .iter()
+                .all(|weight| weight.is_finite() && *weight >= 0.0),
+            "The weights must be finite and non-negative"
+        );
+        let total: f64 = weights.iter().sum();
+        assert!(total > 0.0, "At least one weight must be positive");
+        let mut target = self.uniform() * total;
+        for (index, weight) in weights.iter().enumerate() {
+            if target < *weight {
+                return index;
+            }
+            target -= weight;
+        }
+        // Rounding left the target past the end, fall back to the last positive weight.
+        weights.iter().rposition(|weight| *weight > 0.0).unwrap()
+    }
+
+    /// Shuffle `slice` in place with the Fisher-Yates algorithm.
+    pub fn shuffle<T>(&mut self, slice: &mut [T]) {
+        for last in (1..slice.len()).rev() {
+            slice.swap(last, self.below(last + 1));
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn same_seed_same_stream() {
+        let mut a = SimRng::new(42);
+        let mut b = SimRng::new(42);
+        let mut c = SimRng::new(43);
+        let from_a: Vec<u64> = (0..8).map(|_| a.next_u64()).collect();
+        let from_b: Vec<u64> = (0..8).map(|_| b.next_u64()).collect();
+        let from_c: Vec<u64> = (0..8).map(|_| c.next_u64()).collect();
+        assert_eq!(from_a, from_b);
+        assert_ne!(from_a, from_c);
+    }
+
+    #[test]
+    fn seed_sequence_is_deterministic_and_distinct() {
+        let sequence = SeedSequence::new(42);
+        let seeds: Vec<u64> = (0..1000).map(|k| sequence.seed_for(k)).collect();
+        let again: Vec<u64> = (0..1000).rev().map(|k| sequence.seed_for(k)).collect();
+        assert!(seeds.iter().eq(again.iter().rev()));
+
+        let mut state = 42;
+        assert_eq!(splitmix64(&mut state), seeds[0]);
+        assert_eq!(splitmix64(&mut state), seeds[1]);
+
+        let mut unique = seeds.clone();
+        unique.sort_unstable();
+        unique.dedup();
+        assert_eq!(seeds.len(), unique.len());
+        assert_ne!(seeds[0], SeedSequence::new(43).seed_for(0));
+    }
+
+    #[test]
+    fn uniform_in_unit_interval() {
+        let mut rng = SimRng::new(7);
+        let samples = 10_000;
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            let value = rng.uniform();
+            assert!((0.0..1.0).contains(&value));
+            sum += value;
+        }
+        let mean = sum / samples as f64;
+        assert!((mean - 0.5).abs() < 0.01, "mean = {}", mean);
+    }
+
+    #[test]
+    fn shuffle_is_reproducible() {
+        let shuffled = |seed| {
+            let mut values: Vec<u32> = (0..20).collect();
+            SimRng::new(seed).shuffle(&mut values);
+            values
+        };
+        assert_eq!(shuffled(3), shuffled(3));
+        assert_ne!(shuffled(3), shuffled(4));
+        let mut sorted = shuffled(3);
+        sorted.sort_unstable();
+        assert_eq!((0..20).collect::<Vec<_>>(), sorted);
+    }
+
+    #[test]
+    fn choose_is_roughly_uniform() {
+        let mut rng = SimRng::new(11);
+        assert_eq!(None, rng.choose::<u8>(&[]));
+        let items = ['a', 'b', 'c', 'd'];
+        let draws = 40_000;
+        let mut counts = [0; 4];
+        for _ in 0..draws {
+            let item = rng.choose(&items).unwrap();
+            counts[items.iter().position(|other| other == item).unwrap()] += 1;
+        }
+        for count in counts {
+            let frequency = f64::from(count) / f64::from(draws);
+            assert!((frequency - 0.25).abs() < 0.01, "frequency = {}", frequency);
+        }
+    }
+
+    #[test]
+    fn weighted_choice_follows_the_weights() {
+        let mut rng = SimRng::new(13);
+        let weights = [0.5, 0.0, 0.3, 0.2];
+        let draws = 50_000;
+        let mut counts = [0; 4];
+        for _ in 0..draws {
+            counts[rng.weighted_choice(&weights)] += 1;
+        }
+        for (count, weight) in counts.into_iter().zip(weights) {
+            let frequency = f64::from(count) / f64::from(draws);
+            assert!(
+                (frequency - weight).abs() < 0.01,
+                "frequency = {}",
+                frequency
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "At least one weight must be positive")]
+    fn weighted_choice_rejects_zero_weights() {
+        SimRng::new(1).weighted_choice(&[0.0, 0.0]);
+    }
+}
@@ -0,0 +1,217 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::Duration;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::{GenBoxed, Key, Simulation};
+
+/// Declarative description of a component.
+///
+/// The crate doesn't interpret `kind` or `params`, they are handed to the
+/// factory given to [`Scenario::into_simulation`].
+#[derive(Debug, Clone, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct ComponentSpec {
+    pub name: String,
+    pub kind: String,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub params: BTreeMap<String, f64>,
+}
+
+/// An initial event for the component at `component_index`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EventSpec {
+    pub time_nanos: u64,
+    pub component_index: usize,
+}
+
+/// A list of components and the events that start them.
+#[derive(Debug, Clone, Default, PartialEq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Scenario {
+    pub components: Vec<ComponentSpec>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub events: Vec<EventSpec>,
+}
+
+impl Scenario {
+    /// Build a simulation containing one generator per component, created by `factory`,
+    /// and schedule the initial events.
+    ///
+    /// Components keep the order of `components` so the first one gets the first [Key].
+    /// As with [`Simulation::schedule`] only the first event of each component is kept.
+    ///
+    /// # Panics
+    ///
+    /// Panics if an event refers to a component index out of bounds.
+    pub fn into_simulation(
+        self,
+        factory: impl Fn(&ComponentSpec) -> GenBoxed<()>,
+    ) -> Simulation<()> {
+        let mut simulation = Simulation::default();
+        let keys: Vec<Key> = self
+            .components
+            .iter()
+            .map(|spec| simulation.add_generator(factory(spec)))
+            .collect();
+        for event in self.events {
+            let key = *keys.get(event.component_index).unwrap_or_else(|| {
+                panic!(
+                    "Event refers to component index {} but the scenario has {} components",
+                    event.component_index,
+                    keys.len()
+                )
+            });
+            simulation.schedule(Duration::from_nanos(event.time_nanos), key);
+        }
+        simulation
+    }
+}
+
+/// Recipe for building identical simulations, for example one per replication.
+///
+/// Generators can't be cloned so the template keeps a factory per component
+/// and calls every factory again on each [`ScenarioTemplate::instantiate`].
+#[derive(Default)]
+pub struct ScenarioTemplate {
+    factories: Vec<Box<dyn Fn() -> GenBoxed<()>>>,
+    events: Vec<(Duration, usize)>,
+}
+
+impl fmt::Debug for ScenarioTemplate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScenarioTemplate")
+            .field("components", &self.factories.len())
+            .field("events", &self.events)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ScenarioTemplate {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a component built by `factory`, returning its index.
+    ///
+    /// Components keep the order they were added in so the component at index `i`
+    /// gets the [Key] with id `i` in every instantiated simulation.
+    pub fn add_component(&mut self, factory: impl Fn() -> GenBoxed<()> + 'static) -> usize {
+        self.factories.push(Box::new(factory));
+        self.factories.len() - 1
+    }
+
+    /// Schedule the component at `component_index` at `time` in every instantiated simulation.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `component_index` is out of bounds.
+    pub fn schedule(&mut self, time: Duration, component_index: usize) {
+        assert!(
+            component_index < self.factories.len(),
+            "Event refers to component index {} but the template has {} components",
+            component_index,
+            self.factories.len()
+        );
+        self.events.push((time, component_index));
+    }
+
+    /// Build a fresh simulation with newly created generators and the initial events.
+    #[must_use]
+    pub fn instantiate(&self) -> Simulation<()> {
+        let mut simulation = Simulation::with_capacity(self.factories.len());
+        let keys: Vec<Key> = self
+            .factories
+            .iter()
+            .map(|factory| simulation.add_generator(factory()))
+            .collect();
+        for &(time, component_index) in &self.events {
+            simulation.schedule(time, keys[component_index]);
+        }
+        simulation
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::distributions::Exponential;
+    use crate::rng::SimRng;
+    use crate::Action;
+
+    #[test]
+    fn template_instances_run_identically() {
+        let mut template = ScenarioTemplate::new();
+        for seed in 0..3 {
+            let index = template.add_component(move || {
+                let mut rng = SimRng::new(seed);
+                let exponential = Exponential::new(2.0).unwrap();
+                Box::new(
+                    #[coroutine]
+                    move |_| {
+                        for _ in 0..5 {
+                            yield Action::Hold(exponential.sample(&mut rng));
+                        }
+                    },
+                )
+            });
+            template.schedule(Duration::from_secs(seed), index);
+        }
+
+        let run = || {
+            let mut simulation = template.instantiate();
+            simulation.record_replay();
+            simulation.run_until_empty();
+            assert_eq!(3, simulation.completed_keys().len());
+            simulation.replay_recorder().unwrap().events().to_vec()
+        };
+        let first = run();
+        assert_eq!(18, first.len());
+        assert_eq!(first, run());
+    }
+
+    #[test]
+    #[cfg(feature = "serde")]
+    fn load_and_run_json_scenario() {
+        use std::cell::Cell;
+        use std::rc::Rc;
+
+        let json = r#"{
+            "components": [
+                { "name": "fast", "kind": "worker", "params": { "holds": 2, "secs": 1 } },
+                { "name": "slow", "kind": "worker", "params": { "holds": 1, "secs": 10 } }
+            ],
+            "events": [
+                { "time_nanos": 0, "component_index": 0 },
+                { "time_nanos": 5000000000, "component_index": 1 }
+            ]
+        }"#;
+        let scenario: Scenario = serde_json::from_str(json).unwrap();
+        assert_eq!(2, scenario.components.len());
+
+        let completed = Rc::new(Cell::new(0));
+        let mut simulation = scenario.into_simulation(|spec| {
+            assert_eq!("worker", spec.kind);
+            let holds = spec.params["holds"] as u32;
+            let hold = Duration::from_secs_f64(spec.params["secs"]);
+            let completed = Rc::clone(&completed);
+            Box::new(
+                #[coroutine]
+                move |_| {
+                    for _ in 0..holds {
+                        yield Action::Hold(hold);
+                    }
+                    completed.set(completed.get() + 1);
+                },
+            )
+        });
+        simulation.run_until_empty();
+
+        assert_eq!(2, completed.get());
+        assert_eq!(Duration::from_secs(15), simulation.time());
+    }
+}
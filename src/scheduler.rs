@@ -1,32 +1,93 @@
 use crate::keys::Key;
+use crate::rng::SimRng;
 
 use std::cell::Cell;
 use std::cmp::{Ordering, Reverse};
-use std::collections::BinaryHeap;
+use std::collections::{BTreeMap, BinaryHeap};
+use std::fmt;
 use std::rc::Rc;
 use std::time::Duration;
 
+/// Identifies one of the event queues added with
+/// [`Simulation::add_queue`](crate::Simulation::add_queue).
+///
+/// Queues share the clock, when events of different queues are scheduled at the same time
+/// the one in the queue added first runs first. [`QueueId::DEFAULT`] always goes first.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct QueueId(usize);
+
+impl QueueId {
+    /// The queue used by [`Simulation::schedule`](crate::Simulation::schedule).
+    pub const DEFAULT: QueueId = QueueId(0);
+}
+
+/// How events scheduled at the same time in the same queue are ordered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TieBreak {
+    /// In the order they were scheduled.
+    #[default]
+    Fifo,
+    /// The last one scheduled runs first.
+    Lifo,
+    /// In a random order, reproducible for the same seed.
+    Random(u64),
+}
+
 #[derive(Clone, Debug)]
 pub struct EventEntry {
     time: Reverse<Duration>,
     entity_key: Key,
+    queue: QueueId,
+    /// Priority of the entity when the event was scheduled, lower runs first among
+    /// events at the same time and queue.
+    priority: i32,
+    /// Secondary order among events at the same time, queue and priority, lower runs first.
+    /// Never NaN.
+    sort_key: f64,
+    /// Order among events equal up to the sort key, lower runs first. Set from the [TieBreak].
+    rank: u64,
+    /// Insertion order, breaks ties between equal ranks.
+    sequence: u64,
+    /// User defined tag, doesn't affect the order.
+    category: u16,
 }
 
 impl EventEntry {
-    pub(crate) fn new(time: Duration, entity_key: Key) -> Self {
+    pub(crate) fn new(
+        time: Duration,
+        entity_key: Key,
+        queue: QueueId,
+        sequence: u64,
+        category: u16,
+    ) -> Self {
         Self {
             time: Reverse(time),
             entity_key,
+            queue,
+            priority: 0,
+            sort_key: 0.0,
+            rank: sequence,
+            sequence,
+            category,
         }
     }
     pub fn key(&self) -> Key {
         self.entity_key
     }
+
+    pub fn category(&self) -> u16 {
+        self.category
+    }
+
+    /// Returns the order in which the event was scheduled, counting from zero.
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
 }
 
 impl PartialEq for EventEntry {
     fn eq(&self, other: &Self) -> bool {
-        self.time == other.time
+        self.cmp(other) == Ordering::Equal
     }
 }
 
@@ -34,18 +95,27 @@ impl Eq for EventEntry {}
 
 impl PartialOrd for EventEntry {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        self.time.partial_cmp(&other.time)
+        Some(self.cmp(other))
     }
 }
 
 impl Ord for EventEntry {
+    // BinaryHeap pops the greatest entry so earlier times, queues, priorities, sort keys,
+    // ranks and sequences compare greater.
     fn cmp(&self, other: &Self) -> Ordering {
-        self.time.cmp(&other.time)
+        self.time
+            .cmp(&other.time)
+            .then_with(|| other.queue.cmp(&self.queue))
+            .then_with(|| other.priority.cmp(&self.priority))
+            .then_with(|| other.sort_key.total_cmp(&self.sort_key))
+            .then_with(|| other.rank.cmp(&self.rank))
+            .then_with(|| other.sequence.cmp(&self.sequence))
     }
 }
 
 type Clock = Rc<Cell<Duration>>;
 
+#[derive(Debug, Clone)]
 pub struct ClockRef {
     clock: Clock,
 }
@@ -62,12 +132,44 @@ impl ClockRef {
     pub fn time(&self) -> Duration {
         self.clock.get()
     }
+
+    /// Return the current simulation time in seconds.
+    #[must_use]
+    pub fn secs(&self) -> f64 {
+        self.time().as_secs_f64()
+    }
+
+    /// Return the time elapsed since `earlier`, zero if `earlier` is in the future.
+    #[must_use]
+    pub fn elapsed_since(&self, earlier: Duration) -> Duration {
+        self.time().saturating_sub(earlier)
+    }
+}
+
+/// Formats the current time in seconds with millisecond precision, like `12.500s`.
+impl fmt::Display for ClockRef {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:.3}s", self.secs())
+    }
 }
 
-#[derive(Debug)]
 pub struct Scheduler {
     pub(crate) events: BinaryHeap<EventEntry>,
     clock: Clock,
+    /// The clock goes back to this time on [`Scheduler::reset`].
+    start_time: Duration,
+    queues: Vec<String>,
+    next_sequence: u64,
+    /// Whether a schedule for a key with an event at the identical time is skipped,
+    /// see [`Scheduler::set_coalesce_same_time`].
+    coalesce_same_time: bool,
+    /// Number of schedule calls skipped by `coalesce_same_time`.
+    coalesced: u64,
+    tie_break: TieBreak,
+    /// Only used with [`TieBreak::Random`].
+    rng: SimRng,
+    /// Priorities of the entities that don't have the default of zero.
+    priorities: BTreeMap<Key, i32>,
 }
 
 impl Default for Scheduler {
@@ -75,26 +177,262 @@ impl Default for Scheduler {
         Self {
             events: BinaryHeap::default(),
             clock: Rc::new(Cell::new(Duration::ZERO)),
+            start_time: Duration::ZERO,
+            queues: vec![String::from("default")],
+            next_sequence: 0,
+            coalesce_same_time: false,
+            coalesced: 0,
+            tie_break: TieBreak::default(),
+            rng: SimRng::new(0),
+            priorities: BTreeMap::new(),
         }
     }
 }
 
+impl fmt::Debug for Scheduler {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Scheduler")
+            .field("time", &self.time())
+            .field("pending", &self.pending_sequenced())
+            .finish()
+    }
+}
+
 impl Scheduler {
+    /// Creates a scheduler with space for at least `capacity` pending events.
+    ///
+    /// An entity has at most one pending event per queue, so the number of entities
+    /// that can be scheduled at the same time is a good upper bound with a single queue.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            events: BinaryHeap::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    /// Create a scheduler whose clock starts at `start_time` instead of zero.
+    ///
+    /// Only the clock is shifted, relative times passed to [`Scheduler::schedule`] are
+    /// added to it as usual.
+    #[must_use]
+    pub fn with_start_time(start_time: Duration) -> Self {
+        Self {
+            clock: Rc::new(Cell::new(start_time)),
+            start_time,
+            ..Default::default()
+        }
+    }
+
     /// Schedules `event` to be executed for `entity` at `self.time() + time`.
     ///
     /// `entity_key` is a [`Key`](crate::keys::Key) corresponding to the [Generator](crate::GenBoxed) to be scheduled.
     /// 
     /// If `entity_key` was already scheduled it will ignore the following calls
+    ///
+    /// Times past [`Duration::MAX`] are clamped to it instead of overflowing.
     pub fn schedule(&mut self, time: Duration, entity_key: Key) {
-        let already_inserted = self.events.iter().any(|ev_entry| ev_entry.entity_key == entity_key);
+        self.schedule_in_queue(QueueId::DEFAULT, time, entity_key);
+    }
+
+    /// Adds a new event queue named `name` and returns its id.
+    ///
+    /// At equal times events of this queue run after the ones of every queue added before.
+    pub fn add_queue(&mut self, name: impl Into<String>) -> QueueId {
+        self.queues.push(name.into());
+        QueueId(self.queues.len() - 1)
+    }
+
+    /// Returns the id of the queue named `name`.
+    #[must_use]
+    pub fn queue_id(&self, name: &str) -> Option<QueueId> {
+        self.queues
+            .iter()
+            .position(|queue| queue == name)
+            .map(QueueId)
+    }
+
+    /// Schedules `entity_key` at `self.time() + time` in `queue`.
+    ///
+    /// If `entity_key` was already scheduled in the same queue it will ignore the following calls.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `queue` wasn't added to this scheduler.
+    pub fn schedule_in_queue(&mut self, queue: QueueId, time: Duration, entity_key: Key) {
+        self.push(queue, time, entity_key, 0, 0.0);
+    }
+
+    /// Same as [`Scheduler::schedule`] but the event is tagged with `category`.
+    pub fn schedule_tagged(&mut self, time: Duration, entity_key: Key, category: u16) {
+        self.push(QueueId::DEFAULT, time, entity_key, category, 0.0);
+    }
+
+    /// Same as [`Scheduler::schedule`] but among events at the same time, queue and
+    /// priority the ones with a lower `sort_key` run first, like an earlier deadline.
+    /// Events scheduled without one have a sort key of zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sort_key` is NaN.
+    pub fn schedule_with_key(&mut self, time: Duration, entity_key: Key, sort_key: f64) {
+        assert!(!sort_key.is_nan(), "The sort key of an event can't be NaN");
+        self.push(QueueId::DEFAULT, time, entity_key, 0, sort_key);
+    }
+
+    /// Schedules `entity_key` at the absolute `time`.
+    ///
+    /// If `entity_key` was already scheduled it will ignore the following calls.
+    ///
+    /// Times before [`Scheduler::time`] are clamped to it so the clock never goes back.
+    pub fn schedule_at(&mut self, time: Duration, entity_key: Key) {
+        self.push_at(QueueId::DEFAULT, time, entity_key, 0, 0.0);
+    }
+
+    fn push(
+        &mut self,
+        queue: QueueId,
+        time: Duration,
+        entity_key: Key,
+        category: u16,
+        sort_key: f64,
+    ) {
+        let time = self.time().saturating_add(time);
+        self.push_at(queue, time, entity_key, category, sort_key);
+    }
+
+    fn push_at(
+        &mut self,
+        queue: QueueId,
+        time: Duration,
+        entity_key: Key,
+        category: u16,
+        sort_key: f64,
+    ) {
+        assert!(
+            queue.0 < self.queues.len(),
+            "Unknown event queue {:?}",
+            queue
+        );
+        let time = time.max(self.time());
+        if self.coalesce_same_time
+            && self
+                .events
+                .iter()
+                .any(|ev_entry| ev_entry.entity_key == entity_key && ev_entry.time.0 == time)
+        {
+            self.coalesced += 1;
+            return;
+        }
+        let already_inserted = self
+            .events
+            .iter()
+            .any(|ev_entry| ev_entry.entity_key == entity_key && ev_entry.queue == queue);
         if already_inserted {
             return;
         }
-        let time = self.time() + time;
-        let event = EventEntry::new(time, entity_key);
+        let mut event = EventEntry::new(time, entity_key, queue, self.next_sequence, category);
+        event.priority = self.priority(entity_key);
+        event.sort_key = sort_key;
+        self.assign_order(&mut event);
         self.events.push(event);
     }
 
+    /// Set the priority stamped on the events of `key` scheduled from now on, lower values
+    /// run first among events at the same time and queue. Zero by default.
+    pub fn set_priority(&mut self, key: Key, priority: i32) {
+        if priority == 0 {
+            self.priorities.remove(&key);
+        } else {
+            self.priorities.insert(key, priority);
+        }
+    }
+
+    /// Returns the priority of `key`, see [`Scheduler::set_priority`].
+    #[must_use]
+    pub fn priority(&self, key: Key) -> i32 {
+        self.priorities.get(&key).copied().unwrap_or_default()
+    }
+
+    /// Give `event` the next sequence number and its rank under the tie break policy.
+    fn assign_order(&mut self, event: &mut EventEntry) {
+        event.sequence = self.next_sequence;
+        event.rank = match self.tie_break {
+            TieBreak::Fifo => self.next_sequence,
+            TieBreak::Lifo => u64::MAX - self.next_sequence,
+            TieBreak::Random(_) => self.rng.next_u64(),
+        };
+        self.next_sequence += 1;
+    }
+
+    /// Moves the earliest pending event of `key` to the absolute time `new_time`,
+    /// returns whether there was one.
+    ///
+    /// The event keeps its queue and category and is ordered as if it was scheduled now
+    /// among events at the same time. As with [`Scheduler::schedule_at`] times before
+    /// the clock are clamped to it.
+    pub fn reschedule(&mut self, key: Key, new_time: Duration) -> bool {
+        let mut earliest = None;
+        self.rebuild(|events| {
+            // Greater entries are popped first.
+            let index = events
+                .iter()
+                .enumerate()
+                .filter(|(_, event)| event.entity_key == key)
+                .max_by(|(_, a), (_, b)| a.cmp(b))
+                .map(|(index, _)| index);
+            earliest = index.map(|index| events.swap_remove(index));
+        });
+        let Some(mut event) = earliest else {
+            return false;
+        };
+        event.time = Reverse(new_time.max(self.time()));
+        self.assign_order(&mut event);
+        self.events.push(event);
+        true
+    }
+
+    /// Schedules `key` now in the default queue, moving its pending event there to now if
+    /// it's later instead of ignoring the call like [`Scheduler::schedule_now`].
+    pub fn schedule_now_or_sooner(&mut self, key: Key) {
+        let now = self.time();
+        let later = self.events.iter().any(|event| {
+            event.entity_key == key && event.queue == QueueId::DEFAULT && event.time.0 > now
+        });
+        if !later {
+            self.schedule_now(key);
+            return;
+        }
+        let mut moved = None;
+        self.rebuild(|events| {
+            let index = events
+                .iter()
+                .position(|event| event.entity_key == key && event.queue == QueueId::DEFAULT);
+            moved = index.map(|index| events.swap_remove(index));
+        });
+        if let Some(mut event) = moved {
+            event.time = Reverse(now);
+            self.assign_order(&mut event);
+            self.events.push(event);
+        }
+    }
+
+    /// Apply `edit` to the pending events in no particular order and restore the heap
+    /// order afterwards, in O(n).
+    pub fn rebuild(&mut self, edit: impl FnOnce(&mut Vec<EventEntry>)) {
+        let mut events = std::mem::take(&mut self.events).into_vec();
+        edit(&mut events);
+        self.events = BinaryHeap::from(events);
+    }
+
+    /// Keep only the pending events for which `keep` returns `true` given their time and
+    /// key, returns how many were dropped.
+    pub fn retain(&mut self, mut keep: impl FnMut(Duration, Key) -> bool) -> usize {
+        let before = self.events.len();
+        self.rebuild(|events| events.retain(|event| keep(event.time.0, event.entity_key)));
+        before - self.events.len()
+    }
+
     /// Schedules `event` to be executed for `entity` at `self.time()`.
     ///
     /// `entity` is a [`Key`](crate::key::Key) corresponding to the [Generator](crate::GenBoxed) to be scheduled.
@@ -120,18 +458,127 @@ impl Scheduler {
 
     /// Removes and returns the next scheduled event or `None` if none are left.
     pub fn pop(&mut self) -> Option<EventEntry> {
-        self.events.pop().map(|event| {
+        self.events.pop().inspect(|event| {
             self.clock.replace(event.time.0);
-            event
         })
     }
 
+    /// Returns the next event to be popped without removing it.
+    #[must_use]
+    pub fn peek(&self) -> Option<(Duration, Key)> {
+        self.events
+            .peek()
+            .map(|event| (event.time.0, event.entity_key))
+    }
+
+    /// Returns the number of scheduled events.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Returns `true` if no events are scheduled.
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    /// Skip scheduling a key that already has an event at the identical time, in any
+    /// queue, off by default.
+    ///
+    /// This changes the semantics for components that expect one resume per signal:
+    /// signalling a component several times in the same instant resumes it once.
+    /// The skipped calls are counted, see [`Scheduler::coalesced`].
+    pub fn set_coalesce_same_time(&mut self, coalesce: bool) {
+        self.coalesce_same_time = coalesce;
+    }
+
+    /// Returns how many schedule calls were skipped because the key already had an
+    /// event at the identical time, only counted with [`Scheduler::set_coalesce_same_time`].
+    #[must_use]
+    pub fn coalesced(&self) -> u64 {
+        self.coalesced
+    }
+
+    /// Returns the next `n` events in the order they will be popped, without removing them.
+    #[must_use]
+    pub fn peek_n(&self, n: usize) -> Vec<(Duration, Key)> {
+        let mut next: Vec<&EventEntry> = self.events.iter().collect();
+        // Greater entries are popped first.
+        if n < next.len() {
+            next.select_nth_unstable_by(n, |a, b| b.cmp(a));
+            next.truncate(n);
+        }
+        next.sort_unstable_by(|a, b| b.cmp(a));
+        next.into_iter()
+            .map(|event| (event.time.0, event.entity_key))
+            .collect()
+    }
+
+    /// Returns the earliest time at which `key` is scheduled, in any queue.
+    #[must_use]
+    pub fn next_time_for(&self, key: Key) -> Option<Duration> {
+        self.events
+            .iter()
+            .filter(|event| event.entity_key == key)
+            .map(|event| event.time.0)
+            .min()
+    }
+
+    /// Returns a copy of the scheduled events in the order they will be popped.
+    #[must_use]
+    pub fn pending(&self) -> Vec<(Duration, Key)> {
+        let mut pending: Vec<&EventEntry> = self.events.iter().collect();
+        pending.sort_by(|a, b| b.cmp(a));
+        pending
+            .into_iter()
+            .map(|event| (event.time.0, event.entity_key))
+            .collect()
+    }
+
+    /// Same as [`Scheduler::pending`] with the [sequence](EventEntry::sequence) of each event.
+    #[must_use]
+    pub fn pending_sequenced(&self) -> Vec<(Duration, Key, u64)> {
+        let mut pending: Vec<&EventEntry> = self.events.iter().collect();
+        pending.sort_by(|a, b| b.cmp(a));
+        pending
+            .into_iter()
+            .map(|event| (event.time.0, event.entity_key, event.sequence()))
+            .collect()
+    }
+
+    /// Set how events at the same time and queue scheduled from now on are ordered.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        if let TieBreak::Random(seed) = tie_break {
+            self.rng = SimRng::new(seed);
+        }
+        self.tie_break = tie_break;
+    }
+
+    /// Move the clock forward to `time`, does nothing if `time` isn't after the current time.
+    ///
+    /// The caller must make sure no events are scheduled before `time`.
+    pub fn advance_clock(&mut self, time: Duration) {
+        if time > self.time() {
+            self.clock.set(time);
+        }
+    }
+
+    /// Drop every pending event, the clock is left as is.
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+
+    /// Drop every pending event and set the clock back to its start time, queues are kept.
+    pub fn reset(&mut self) {
+        self.clear();
+        self.clock.set(self.start_time);
+        self.next_sequence = 0;
+    }
+
     pub fn remove(&mut self, key: Key) -> bool {
         if !self.events.iter().any(|event_entry| event_entry.key() == key) { return false };
-        let mut events = std::mem::take(&mut self.events).into_vec();
-        events.retain(|event_entry| event_entry.key() != key);
-        let events = BinaryHeap::from(events);
-        self.events = events;
+        self.retain(|_, other| other != key);
         true
     }
 
@@ -164,32 +611,68 @@ mod test {
         assert_eq!(
             EventEntry {
                 time: Reverse(Duration::from_secs(1)),
-                entity_key: Key::new(2)
+                entity_key: Key::new(2),
+                queue: QueueId::DEFAULT,
+                rank: 0,
+                sequence: 0,
+                priority: 0,
+                sort_key: 0.0,
+                category: 0,
             },
             EventEntry {
                 time: Reverse(Duration::from_secs(1)),
-                entity_key: Key::new(2)
+                entity_key: Key::new(2),
+                queue: QueueId::DEFAULT,
+                rank: 0,
+                sequence: 0,
+                priority: 0,
+                sort_key: 0.0,
+                category: 0,
             }
         );
         assert_eq!(
             EventEntry {
                 time: Reverse(Duration::from_secs(0)),
-                entity_key: Key::new(2)
+                entity_key: Key::new(2),
+                queue: QueueId::DEFAULT,
+                rank: 0,
+                sequence: 0,
+                priority: 0,
+                sort_key: 0.0,
+                category: 0,
             }
             .cmp(&EventEntry {
                 time: Reverse(Duration::from_secs(1)),
-                entity_key: Key::new(2)
+                entity_key: Key::new(2),
+                queue: QueueId::DEFAULT,
+                rank: 0,
+                sequence: 0,
+                priority: 0,
+                sort_key: 0.0,
+                category: 0,
             }),
             Ordering::Greater
         );
         assert_eq!(
             EventEntry {
                 time: Reverse(Duration::from_secs(2)),
-                entity_key: Key::new(2)
+                entity_key: Key::new(2),
+                queue: QueueId::DEFAULT,
+                rank: 0,
+                sequence: 0,
+                priority: 0,
+                sort_key: 0.0,
+                category: 0,
             }
             .cmp(&EventEntry {
                 time: Reverse(Duration::from_secs(1)),
-                entity_key: Key::new(2)
+                entity_key: Key::new(2),
+                queue: QueueId::DEFAULT,
+                rank: 0,
+                sequence: 0,
+                priority: 0,
+                sort_key: 0.0,
+                category: 0,
             }),
             Ordering::Less
         );
@@ -260,6 +743,12 @@ mod test {
             EventEntry {
                 time: Reverse(Duration::from_secs(x) + clock_ref.time()),
                 entity_key: Key::new(key_id),
+                queue: QueueId::DEFAULT,
+                rank: 0,
+                sequence: 0,
+                priority: 0,
+                sort_key: 0.0,
+                category: 0,
             }
         };
         let event_1 = make_event_entry(4); 
@@ -283,4 +772,366 @@ mod test {
         assert_eq!(None, r_event); 
         assert_eq!(Duration::from_secs(4), scheduler.time()); 
     }
+
+    #[test]
+    fn queues_share_the_clock() {
+        let mut scheduler = Scheduler::default();
+        let control = scheduler.add_queue("control");
+        assert_eq!(Some(QueueId::DEFAULT), scheduler.queue_id("default"));
+        assert_eq!(Some(control), scheduler.queue_id("control"));
+        assert_eq!(None, scheduler.queue_id("physical"));
+
+        let seconds = Duration::from_secs;
+        scheduler.schedule_in_queue(control, seconds(1), Key::new(0));
+        scheduler.schedule_in_queue(control, seconds(1), Key::new(1));
+        scheduler.schedule(seconds(1), Key::new(2));
+        scheduler.schedule(seconds(1), Key::new(3));
+        scheduler.schedule_in_queue(control, seconds(0), Key::new(4));
+        scheduler.schedule(seconds(2), Key::new(5));
+        // The same key can be scheduled once per queue.
+        scheduler.schedule_in_queue(control, seconds(2), Key::new(5));
+        scheduler.schedule(seconds(3), Key::new(5));
+
+        let mut popped = Vec::new();
+        while let Some(event) = scheduler.pop() {
+            popped.push((scheduler.time(), event.key().id(), event.queue));
+        }
+        let default = QueueId::DEFAULT;
+        assert_eq!(
+            vec![
+                (seconds(0), 4, control),
+                (seconds(1), 2, default),
+                (seconds(1), 3, default),
+                (seconds(1), 0, control),
+                (seconds(1), 1, control),
+                (seconds(2), 5, default),
+                (seconds(2), 5, control),
+            ],
+            popped
+        );
+    }
+
+    #[test]
+    fn with_capacity_preallocates() {
+        let scheduler = Scheduler::with_capacity(32);
+        assert!(scheduler.events.is_empty());
+        assert!(scheduler.events.capacity() >= 32);
+        assert_eq!(Duration::ZERO, scheduler.time());
+    }
+
+    #[test]
+    fn pending_is_sorted_by_time() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(Duration::from_secs(7), Key::new(0));
+        scheduler.schedule(Duration::from_secs(2), Key::new(1));
+        scheduler.schedule(Duration::from_secs(5), Key::new(2));
+        scheduler.schedule_now(Key::new(3));
+
+        let expected = vec![
+            (Duration::ZERO, Key::new(3)),
+            (Duration::from_secs(2), Key::new(1)),
+            (Duration::from_secs(5), Key::new(2)),
+            (Duration::from_secs(7), Key::new(0)),
+        ];
+        assert_eq!(expected, scheduler.pending());
+        // Inspecting the queue doesn't consume it.
+        assert_eq!(4, scheduler.events.len());
+
+        let debug = format!("{:?}", scheduler);
+        assert!(debug.starts_with("Scheduler { time: 0ns, pending: [(0ns, Key { id: 3 }, 3), (2s"));
+    }
+
+    #[test]
+    fn peek_n_returns_the_earliest_events() {
+        let mut scheduler = Scheduler::default();
+        for (id, secs) in [(0, 4), (1, 1), (2, 5), (3, 3), (4, 2)] {
+            scheduler.schedule(Duration::from_secs(secs), Key::new(id));
+        }
+
+        let expected = vec![
+            (Duration::from_secs(1), Key::new(1)),
+            (Duration::from_secs(2), Key::new(4)),
+            (Duration::from_secs(3), Key::new(3)),
+        ];
+        assert_eq!(expected, scheduler.peek_n(3));
+        assert_eq!(scheduler.pending(), scheduler.peek_n(10));
+        assert!(scheduler.peek_n(0).is_empty());
+        assert_eq!(5, scheduler.len());
+        assert_eq!(Duration::ZERO, scheduler.time());
+    }
+
+    #[test]
+    fn tie_break_policies() {
+        let pop_order = |tie_break| {
+            let mut scheduler = Scheduler::default();
+            scheduler.set_tie_break(tie_break);
+            for id in 0..6 {
+                scheduler.schedule(Duration::from_secs(1), Key::new(id));
+            }
+            scheduler.schedule_now(Key::new(6));
+            std::iter::from_fn(|| scheduler.pop().map(|event| event.key().id())).collect::<Vec<_>>()
+        };
+
+        assert_eq!(vec![6, 0, 1, 2, 3, 4, 5], pop_order(TieBreak::Fifo));
+        assert_eq!(vec![6, 5, 4, 3, 2, 1, 0], pop_order(TieBreak::Lifo));
+        assert_eq!(vec![6, 0, 1, 2, 3, 4, 5], pop_order(TieBreak::default()));
+
+        let random = pop_order(TieBreak::Random(3));
+        assert_eq!(random, pop_order(TieBreak::Random(3)));
+        // Time still comes first.
+        assert_eq!(6, random[0]);
+        let mut sorted = random.clone();
+        sorted.sort_unstable();
+        assert_eq!(vec![0, 1, 2, 3, 4, 5, 6], sorted);
+        assert_ne!(pop_order(TieBreak::Fifo), random);
+    }
+
+    #[test]
+    fn scheduling_clamps_instead_of_overflowing() {
+        let mut scheduler = Scheduler::default();
+        scheduler.advance_clock(Duration::from_secs(5));
+
+        scheduler.schedule(Duration::MAX, Key::new(0));
+        scheduler.schedule(Duration::MAX - Duration::from_secs(1), Key::new(1));
+        scheduler.schedule_at(Duration::MAX, Key::new(2));
+        scheduler.schedule_at(Duration::from_secs(2), Key::new(3));
+
+        let expected = vec![
+            (Duration::from_secs(5), Key::new(3)),
+            (Duration::MAX, Key::new(0)),
+            (Duration::MAX, Key::new(1)),
+            (Duration::MAX, Key::new(2)),
+        ];
+        assert_eq!(expected, scheduler.pending());
+        scheduler.pop();
+        assert_eq!(Duration::from_secs(5), scheduler.time());
+    }
+
+    #[test]
+    fn next_time_for_returns_the_earliest_event_of_a_key() {
+        let mut scheduler = Scheduler::default();
+        let control = scheduler.add_queue("control");
+        scheduler.schedule(Duration::from_secs(7), Key::new(0));
+        scheduler.schedule_in_queue(control, Duration::from_secs(3), Key::new(0));
+        scheduler.schedule(Duration::from_secs(1), Key::new(1));
+
+        assert_eq!(
+            Some(Duration::from_secs(3)),
+            scheduler.next_time_for(Key::new(0))
+        );
+        assert_eq!(
+            Some(Duration::from_secs(1)),
+            scheduler.next_time_for(Key::new(1))
+        );
+        assert_eq!(None, scheduler.next_time_for(Key::new(2)));
+        assert_eq!(3, scheduler.len());
+    }
+
+    #[test]
+    fn clock_ref_helpers() {
+        let mut scheduler = Scheduler::default();
+        let clock = scheduler.clock();
+        scheduler.advance_clock(Duration::from_millis(12_500));
+
+        assert_eq!(12.5, clock.secs());
+        assert_eq!(
+            Duration::from_millis(2_500),
+            clock.elapsed_since(Duration::from_secs(10))
+        );
+        assert_eq!(Duration::ZERO, clock.elapsed_since(Duration::from_secs(20)));
+        assert_eq!("12.500s", clock.to_string());
+    }
+
+    #[test]
+    fn start_time_shifts_the_clock() {
+        let mut scheduler = Scheduler::with_start_time(Duration::from_secs(100));
+        assert_eq!(Duration::from_secs(100), scheduler.time());
+
+        scheduler.schedule(Duration::from_secs(5), Key::new(0));
+        assert_eq!(
+            vec![(Duration::from_secs(105), Key::new(0))],
+            scheduler.pending()
+        );
+        scheduler.pop();
+        assert_eq!(Duration::from_secs(105), scheduler.time());
+
+        scheduler.reset();
+        assert_eq!(Duration::from_secs(100), scheduler.time());
+    }
+
+    #[test]
+    fn duplicate_schedules_are_coalesced() {
+        let mut scheduler = Scheduler::default();
+        scheduler.set_coalesce_same_time(true);
+        for _ in 0..3 {
+            scheduler.schedule_now(Key::new(0));
+        }
+        // Ignored because the key is already scheduled in the queue, but not coalesced.
+        scheduler.schedule(Duration::from_secs(1), Key::new(0));
+        scheduler.schedule_now(Key::new(1));
+
+        assert_eq!(2, scheduler.coalesced());
+        assert_eq!(
+            vec![(Duration::ZERO, Key::new(0)), (Duration::ZERO, Key::new(1))],
+            scheduler.pending()
+        );
+    }
+
+    #[test]
+    fn schedule_now_or_sooner_moves_a_later_event() {
+        let mut scheduler = Scheduler::default();
+        let timeouts = scheduler.add_queue("timeouts");
+        scheduler.schedule(Duration::from_secs(3), Key::new(0));
+        scheduler.schedule_in_queue(timeouts, Duration::from_secs(5), Key::new(0));
+        scheduler.schedule_now(Key::new(1));
+
+        scheduler.schedule_now_or_sooner(Key::new(0));
+        scheduler.schedule_now_or_sooner(Key::new(1));
+
+        let secs = Duration::from_secs;
+        assert_eq!(
+            vec![
+                (secs(0), Key::new(1)),
+                (secs(0), Key::new(0)),
+                (secs(5), Key::new(0))
+            ],
+            scheduler.pending()
+        );
+    }
+
+    #[test]
+    fn coalescing_is_off_by_default() {
+        let mut scheduler = Scheduler::default();
+        let control = scheduler.add_queue("control");
+        scheduler.schedule_now(Key::new(0));
+        scheduler.schedule_now(Key::new(0));
+        scheduler.schedule_in_queue(control, Duration::ZERO, Key::new(0));
+        assert_eq!(0, scheduler.coalesced());
+        assert_eq!(2, scheduler.len());
+
+        // With coalescing the same time in another queue is skipped too.
+        scheduler.set_coalesce_same_time(true);
+        let physical = scheduler.add_queue("physical");
+        scheduler.schedule_in_queue(physical, Duration::ZERO, Key::new(0));
+        assert_eq!(1, scheduler.coalesced());
+        assert_eq!(2, scheduler.len());
+    }
+
+    #[test]
+    fn sequences_record_the_insertion_order() {
+        let mut scheduler = Scheduler::default();
+        for (id, secs) in [3, 1, 2].into_iter().enumerate() {
+            scheduler.schedule(Duration::from_secs(secs), Key::new(id));
+        }
+        scheduler.schedule_now(Key::new(3));
+
+        let secs = Duration::from_secs;
+        let expected = vec![
+            (secs(0), Key::new(3), 3),
+            (secs(1), Key::new(1), 1),
+            (secs(2), Key::new(2), 2),
+            (secs(3), Key::new(0), 0),
+        ];
+        assert_eq!(expected, scheduler.pending_sequenced());
+        let first = scheduler.pop().unwrap();
+        assert_eq!(3, first.sequence());
+        scheduler.schedule(secs(1), Key::new(3));
+        assert_eq!(
+            Some(&(secs(1), Key::new(3), 4)),
+            scheduler.pending_sequenced().get(1)
+        );
+        assert!(format!("{:?}", scheduler).contains("(1s, Key { id: 3 }, 4)"));
+    }
+
+    #[test]
+    fn retain_drops_events_and_keeps_the_order() {
+        let mut scheduler = Scheduler::default();
+        let urgent = scheduler.add_queue("urgent");
+        let secs = Duration::from_secs;
+        for (id, time) in [(0, 4), (1, 1), (2, 3), (3, 2)] {
+            scheduler.schedule(secs(time), Key::new(id));
+        }
+        scheduler.schedule_in_queue(urgent, secs(2), Key::new(1));
+
+        assert_eq!(2, scheduler.retain(|_, key| key != Key::new(1)));
+        assert_eq!(0, scheduler.retain(|_, key| key != Key::new(1)));
+        let popped: Vec<Key> =
+            std::iter::from_fn(|| scheduler.pop().map(|event| event.key())).collect();
+        assert_eq!(vec![Key::new(3), Key::new(2), Key::new(0)], popped);
+    }
+
+    #[test]
+    fn priorities_break_ties_before_the_tie_break() {
+        let mut scheduler = Scheduler::default();
+        scheduler.set_priority(Key::new(1), -1);
+        scheduler.set_priority(Key::new(2), 5);
+        scheduler.set_priority(Key::new(2), 0);
+        for id in 0..3 {
+            scheduler.schedule(Duration::from_secs(1), Key::new(id));
+        }
+        scheduler.schedule_now(Key::new(3));
+
+        let popped: Vec<Key> =
+            std::iter::from_fn(|| scheduler.pop().map(|event| event.key())).collect();
+        assert_eq!(
+            vec![Key::new(3), Key::new(1), Key::new(0), Key::new(2)],
+            popped
+        );
+        assert_eq!(0, scheduler.priority(Key::new(2)));
+    }
+
+    #[test]
+    fn sort_keys_order_events_at_the_same_time() {
+        let mut scheduler = Scheduler::default();
+        let secs = Duration::from_secs;
+        for (id, deadline) in [(0, 3.5), (1, -1.0), (2, 2.25)] {
+            scheduler.schedule_with_key(secs(1), Key::new(id), deadline);
+        }
+        scheduler.schedule(secs(1), Key::new(3));
+
+        let popped: Vec<Key> =
+            std::iter::from_fn(|| scheduler.pop().map(|event| event.key())).collect();
+        assert_eq!(
+            vec![Key::new(1), Key::new(3), Key::new(2), Key::new(0)],
+            popped
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "The sort key of an event can't be NaN")]
+    fn nan_sort_keys_are_rejected() {
+        Scheduler::default().schedule_with_key(Duration::ZERO, Key::new(0), f64::NAN);
+    }
+
+    #[test]
+    fn partial_cmp_agrees_with_cmp() {
+        let entry = |secs, queue, priority, sort_key, rank, sequence| EventEntry {
+            time: Reverse(Duration::from_secs(secs)),
+            entity_key: Key::new(0),
+            queue: QueueId(queue),
+            priority,
+            sort_key,
+            rank,
+            sequence,
+            category: 0,
+        };
+        let entries = [
+            entry(1, 0, 0, 0.0, 0, 0),
+            entry(1, 0, 0, 0.0, 0, 1),
+            entry(1, 0, 0, 0.0, 1, 0),
+            entry(1, 0, 0, -0.5, 2, 2),
+            entry(1, 0, -1, 0.0, 3, 3),
+            entry(1, 1, 0, 0.0, 0, 0),
+            entry(0, 0, 0, 0.0, 4, 4),
+            entry(2, 0, 0, 0.0, 0, 0),
+        ];
+        for a in &entries {
+            for b in &entries {
+                assert_eq!(Some(a.cmp(b)), a.partial_cmp(b));
+                assert_eq!(a.cmp(b) == Ordering::Equal, a == b);
+            }
+        }
+        // Equal times differing only by sequence aren't equal.
+        assert_eq!(Ordering::Greater, entries[0].cmp(&entries[1]));
+    }
 }
@@ -1,45 +1,359 @@
 use std::cell::Cell;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use std::fmt;
 use std::ops::CoroutineState;
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
-use crate::container::{Container, EntityState};
-use crate::scheduler::Scheduler;
+use crate::container::{Container, EntityState, StateCounts, WakeReason};
+use crate::graph::ActivationGraph;
+use crate::group::{Group, GroupId};
+use crate::keys::{KeyBufferPool, KeySet, TypedKey};
+use crate::pacing::{Pacer, SystemClock, WallClock};
+use crate::replay::{ReplayMismatch, ReplayRecorder};
+use crate::resources::Mailbox;
+use crate::scheduler::{QueueId, Scheduler, TieBreak};
 use crate::state::State;
-use crate::{Action, GenBoxed, Key};
+use crate::stats::{EngineProfiler, SimulationStats, SojournRecorder};
+use crate::timeline::StateTimeline;
+use crate::{Action, ActionKind, GenBoxed, Key};
 
-pub struct Simulation<R> {
-    scheduler: Scheduler,
-    entities: Container<R>,
+pub struct Simulation<R, O = ()> {
+    pub(crate) scheduler: Scheduler,
+    entities: Container<R, O>,
     state: Rc<Cell<State>>,
+    activation_graph: Option<ActivationGraph>,
+    key_buffers: KeyBufferPool,
+    stats: SimulationStats,
+    replay: Option<ReplayRecorder>,
+    timeline: Option<StateTimeline>,
+    profiler: Option<EngineProfiler>,
+    sojourns: Option<SojournRecorder>,
+    on_idle: Option<IdleHook<R, O>>,
+    step_observers: Vec<StepObserver>,
+    speed_factor: f64,
+    reject_past_events: bool,
+    /// Whether invariants are checked after every step, see [`Simulation::set_debug_checks`].
+    debug_checks: bool,
+    breakpoints: BTreeSet<Key>,
+    watchers: BTreeMap<Key, Vec<Watcher>>,
+    groups: Vec<Group>,
+    /// Callbacks of the timer entities added by [`Simulation::schedule_recurring`].
+    recurring: BTreeMap<Key, RecurringHook<R, O>>,
+    /// What the last processed event did, see [`Simulation::step_with_info`].
+    last_step: Option<StepInfo>,
+    /// Set after stopping at a breakpoint so the next step processes the event.
+    resume_from_breakpoint: Option<Key>,
 }
 
+type IdleHook<R, O> = Box<dyn FnMut(&mut Simulation<R, O>)>;
+type RecurringHook<R, O> = Box<dyn FnMut(&mut Simulation<R, O>)>;
+type StepObserver = Box<dyn FnMut(Duration, Key)>;
+type Watcher = Box<dyn FnMut(Key, EntityState, EntityState)>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum ShouldContinue {
     Advance,
     Break,
+    /// The next event belongs to an entity with a [breakpoint](Simulation::set_breakpoint),
+    /// it wasn't processed yet.
+    Breakpoint(Key),
+}
+
+/// Description of a processed event, returned by [`Simulation::step_with_info`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StepInfo {
+    /// The entity that was resumed.
+    pub key: Key,
+    /// The simulation time of the event.
+    pub time: Duration,
+    /// What the entity did once resumed.
+    pub action_kind: ActionKind,
+}
+
+/// Error returned when scheduling an event before the current time,
+/// see [`Simulation::set_reject_past_events`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PastEventError {
+    /// The entity that would have been scheduled.
+    pub key: Key,
+    /// The requested time.
+    pub time: Duration,
+    /// The simulation time when the event was scheduled.
+    pub now: Duration,
+}
+
+impl fmt::Display for PastEventError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Entity ID = {} was scheduled at {:?} but the simulation is at {:?}",
+            self.key.id, self.time, self.now
+        )
+    }
+}
+
+impl Error for PastEventError {}
+
+/// Identifies a callback added with [`Simulation::schedule_recurring`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct RecurringId(Key);
+
+impl RecurringId {
+    /// Returns the key of the timer entity calling the callback.
+    #[must_use]
+    pub fn key(self) -> Key {
+        self.0
+    }
+}
+
+/// Summary of a run that stops at a deadline or after a number of events, see
+/// [`Simulation::run_until`] and [`Simulation::run_chunked`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunOutcome {
+    /// Number of events processed during the run.
+    pub processed: usize,
+    /// Number of events still scheduled when the run stopped.
+    pub remaining: usize,
+    /// Simulation time when the run stopped.
+    pub stopped_at: Duration,
+    /// The entity whose [breakpoint](Simulation::set_breakpoint) stopped the run, its
+    /// event wasn't processed yet.
+    pub breakpoint: Option<Key>,
 }
 
-impl<R> Default for Simulation<R>
+impl RunOutcome {
+    /// Returns `true` if the run stopped because no events were left.
+    #[must_use]
+    pub fn drained(&self) -> bool {
+        self.remaining == 0
+    }
+}
+
+/// Error returned by [`Simulation::try_step_with`] when a yielded action can't be applied.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepError {
+    /// The action targeted an entity that isn't in the simulation or has completed.
+    MissingComponent(Key),
+    /// The action tried to activate an entity that was already active.
+    AlreadyActive { activator: Key, target: Key },
+    /// The entity yielded an [`Action::Multi`] with a nested `Multi` or more than one
+    /// action deciding when the caller resumes, none of its actions were applied.
+    InvalidMulti(Key),
+    /// The entity's generator panicked and was removed from the simulation.
+    ///
+    /// Only returned with the `catch-panics` feature, otherwise the panic unwinds
+    /// through [`Simulation::try_step_with`].
+    ComponentPanicked(Key),
+}
+
+impl fmt::Display for StepError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StepError::MissingComponent(key) => {
+                write!(
+                    f,
+                    "Entity ID = {} isn't in the simulation or has completed",
+                    key.id
+                )
+            }
+            StepError::AlreadyActive { activator, target } => write!(
+                f,
+                "Entity ID = {} tried to Activate Entity ID = {} but it was already active",
+                activator.id, target.id
+            ),
+            StepError::InvalidMulti(key) => write!(
+                f,
+                "Entity ID = {} yielded a Multi with a nested Multi or conflicting actions",
+                key.id
+            ),
+            StepError::ComponentPanicked(key) => {
+                write!(f, "Entity ID = {} panicked and was removed", key.id)
+            }
+        }
+    }
+}
+
+impl Error for StepError {}
+
+impl<R, O> Default for Simulation<R, O>
 where
     R: 'static,
+    O: 'static,
 {
     fn default() -> Self {
         Self {
             scheduler: Scheduler::default(),
             entities: Container::default(),
-            state: Rc::new(Cell::new(State::default()))
+            state: Rc::new(Cell::new(State::default())),
+            activation_graph: None,
+            key_buffers: KeyBufferPool::default(),
+            stats: SimulationStats::default(),
+            replay: None,
+            timeline: None,
+            profiler: None,
+            sojourns: None,
+            on_idle: None,
+            step_observers: Vec::new(),
+            speed_factor: 1.0,
+            reject_past_events: false,
+            debug_checks: false,
+            breakpoints: BTreeSet::new(),
+            watchers: BTreeMap::new(),
+            groups: Vec::new(),
+            recurring: BTreeMap::new(),
+            last_step: None,
+            resume_from_breakpoint: None,
         }
     }
 }
 
-impl<R> Simulation<R>
+impl<R, O> fmt::Debug for Simulation<R, O>
+where
+    R: 'static,
+    O: 'static,
+{
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Simulation")
+            .field("time", &self.time())
+            .field("pending_events", &self.scheduler.len())
+            .field("entities", &self.entities.len())
+            .field("states", &self.state_counts())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R, O> Simulation<R, O>
 where
     R: 'static,
+    O: 'static,
 {
+    /// Creates a simulation with space for at least `capacity` generators
+    /// and `capacity` pending events.
+    ///
+    /// Useful when the number of entities is known up front. Since an entity
+    /// is scheduled at most once per queue, with a single queue there are never
+    /// more pending events than entities.
+    #[must_use]
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            scheduler: Scheduler::with_capacity(capacity),
+            entities: Container::with_capacity(capacity),
+            ..Default::default()
+        }
+    }
+
+    /// Create a simulation whose clock starts at `start_time` instead of zero, for
+    /// example to continue the timeline of another simulation.
+    ///
+    /// Only the clock is shifted: [`Simulation::schedule`] places events at
+    /// `start_time + offset` and [`Simulation::reset`] goes back to `start_time`.
+    #[must_use]
+    pub fn with_start_time(start_time: Duration) -> Self {
+        Self {
+            scheduler: Scheduler::with_start_time(start_time),
+            ..Default::default()
+        }
+    }
+
     /// Add an already constructed Generator into the simulation.
     #[inline]
-    pub fn add_generator(&mut self, gen: GenBoxed<R>) -> Key {
-        self.entities.add_generator(gen)
+    pub fn add_generator(&mut self, gen: GenBoxed<R, O>) -> Key {
+        let key = self.entities.add_generator(gen);
+        self.record_added(key, EntityState::Active);
+        key
+    }
+
+    /// Add a generator and schedule its first resume `at` from now, as with
+    /// [`Simulation::schedule`].
+    pub fn add_and_schedule(&mut self, gen: GenBoxed<R, O>, at: Duration) -> Key {
+        let key = self.add_generator(gen);
+        self.schedule(at, key);
+        key
+    }
+
+    /// Add the generator built by `factory`, which is given the key the entity gets so
+    /// the generator can refer to itself, for example to request a resource.
+    pub fn add_generator_with_key(&mut self, factory: impl FnOnce(Key) -> GenBoxed<R, O>) -> Key {
+        let key = self.entities.next_key();
+        let gen = factory(key);
+        let added = self.add_generator(gen);
+        debug_assert_eq!(key, added);
+        added
+    }
+
+    /// Add a generator whose events run before the ones of lower priority entities
+    /// scheduled at the same time, see [`Simulation::set_priority`].
+    pub fn add_generator_with_priority(&mut self, gen: GenBoxed<R, O>, priority: i32) -> Key {
+        let key = self.add_generator(gen);
+        self.scheduler.set_priority(key, priority);
+        key
+    }
+
+    /// Set the priority of the entity associated with `key`, lower values run first among
+    /// events at the same time and queue. Entities have priority zero by default.
+    ///
+    /// Applies to the events scheduled from now on, whatever schedules them. The priority
+    /// is dropped when the entity is removed or its slot is reaped.
+    pub fn set_priority(&mut self, key: Key, priority: i32) {
+        self.scheduler.set_priority(key, priority);
+    }
+
+    /// Returns the priority of the entity associated with `key`, see [`Simulation::set_priority`].
+    #[must_use]
+    pub fn priority(&self, key: Key) -> i32 {
+        self.scheduler.priority(key)
+    }
+
+    /// Add a generator and return its key tagged with the kind of entity `K`.
+    #[inline]
+    pub fn add_generator_typed<K>(&mut self, gen: GenBoxed<R, O>) -> TypedKey<K> {
+        TypedKey::new(self.add_generator(gen))
+    }
+
+    /// Remove the entity associated with `key` from the simulation and return its
+    /// generator together with its state, its pending events are cancelled.
+    ///
+    /// Returns `None` if the entity completed or was already removed.
+    /// The generator keeps its progress and can be put back with [`Simulation::reattach`].
+    pub fn detach(&mut self, key: Key) -> Option<(GenBoxed<R, O>, EntityState)> {
+        let detached = self.entities.remove(key)?;
+        self.scheduler.remove(key);
+        self.scheduler.set_priority(key, 0);
+        Some(detached)
+    }
+
+    /// Add a generator previously [detached](Simulation::detach) back into the simulation.
+    ///
+    /// The entity gets a new [Key] and isn't scheduled, an active entity must be
+    /// scheduled again to be resumed.
+    pub fn reattach(&mut self, gen: GenBoxed<R, O>, state: EntityState) -> Key {
+        let key = self.entities.insert(gen, state);
+        self.record_added(key, state);
+        key
+    }
+
+    fn record_added(&mut self, key: Key, state: EntityState) {
+        if let Some(timeline) = &mut self.timeline {
+            timeline.record(key, self.scheduler.time(), state);
+        }
+        if let Some(sojourns) = &mut self.sojourns {
+            sojourns.enter(key, self.scheduler.time());
+        }
+    }
+
+    /// Move the entity associated with `key` into `other`, returning its key there.
+    ///
+    /// The entity's events in `self` are cancelled and it keeps its state, an active
+    /// entity must be scheduled in `other` to be resumed. Returns `None` if the entity
+    /// completed or was already removed.
+    ///
+    /// The clocks of both simulations are independent, an entity that captured a
+    /// [ClockRef](crate::ClockRef) or computed times from `self` keeps seeing those.
+    pub fn transfer_to(&mut self, key: Key, other: &mut Simulation<R, O>) -> Option<Key> {
+        let (gen, state) = self.detach(key)?;
+        Some(other.reattach(gen, state))
     }
 
     /// Schedules `entity_key` at `self.time() + time`.
@@ -47,11 +361,149 @@ where
     /// `entity_key` is a [Key] corresponding to the entity to be scheduled.
     /// 
     /// If `entity_key` was already scheduled it will ignore the following calls
+    ///
+    /// Times past [`Duration::MAX`] are clamped to it instead of overflowing.
     #[inline]
     pub fn schedule(&mut self, time: Duration, entity_key: Key) {
         self.scheduler.schedule(time, entity_key)
     }
 
+    /// Set how events scheduled at the same time in the same queue are ordered,
+    /// [`TieBreak::Fifo`] by default.
+    ///
+    /// Only affects the events scheduled afterwards.
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.scheduler.set_tie_break(tie_break);
+    }
+
+    /// Schedules `entity_key` at the absolute `time`.
+    ///
+    /// If `entity_key` was already scheduled it will ignore the following calls.
+    ///
+    /// Scheduling before [`Simulation::time`] is a modeling error: it panics in debug
+    /// builds, otherwise the event is clamped to the current time.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [PastEventError] if `time` is in the past and the check was enabled
+    /// with [`Simulation::set_reject_past_events`], nothing is scheduled then.
+    pub fn schedule_at(&mut self, time: Duration, entity_key: Key) -> Result<(), PastEventError> {
+        let now = self.time();
+        if time < now {
+            let error = PastEventError {
+                key: entity_key,
+                time,
+                now,
+            };
+            if self.reject_past_events {
+                return Err(error);
+            }
+            debug_assert!(false, "{}", error);
+        }
+        self.scheduler.schedule_at(time, entity_key);
+        Ok(())
+    }
+
+    /// Returns the earliest time at which `entity_key` is scheduled, `None` if it isn't.
+    #[must_use]
+    pub fn next_time_for(&self, entity_key: Key) -> Option<Duration> {
+        self.scheduler.next_time_for(entity_key)
+    }
+
+    /// Moves the earliest pending event of `entity_key` to the absolute time `new_time`,
+    /// returns whether the entity had one.
+    ///
+    /// Useful to push a timeout further out without cancelling and scheduling the entity
+    /// again. Times before [`Simulation::time`] are clamped to it.
+    pub fn reschedule(&mut self, entity_key: Key, new_time: Duration) -> bool {
+        self.scheduler.reschedule(entity_key, new_time)
+    }
+
+    /// Keep only the pending events for which `keep` returns `true` given their time and
+    /// key, returns how many were dropped.
+    ///
+    /// Entities whose event was dropped keep their state, an active one stays without
+    /// a pending event until it is scheduled again.
+    pub fn retain_events(&mut self, keep: impl FnMut(Duration, Key) -> bool) -> usize {
+        self.scheduler.retain(keep)
+    }
+
+    /// Make [`Simulation::schedule_at`] return an error instead of scheduling events in the past.
+    ///
+    /// Off by default.
+    pub fn set_reject_past_events(&mut self, reject: bool) {
+        self.reject_past_events = reject;
+    }
+
+    /// Check the consistency of the simulation after every step.
+    ///
+    /// The step panics describing the first violated invariant: an entity whose state
+    /// disagrees with its generator having completed, a pending event for a removed
+    /// entity, the clock going backwards or a freed slot still in use.
+    ///
+    /// Off by default, every check walks all the entities and pending events.
+    pub fn set_debug_checks(&mut self, enabled: bool) {
+        self.debug_checks = enabled;
+    }
+
+    /// Schedules `entity_key` at `self.time() + time` only if the entity is still in the
+    /// simulation and active, returns whether it was scheduled.
+    ///
+    /// Completed or removed entities can't be resumed and passive ones must be activated instead.
+    /// As with [`Simulation::schedule`] an entity already scheduled keeps its pending event.
+    pub fn schedule_if_alive(&mut self, time: Duration, entity_key: Key) -> bool {
+        if self.entity_state(entity_key) != Some(EntityState::Active) {
+            return false;
+        }
+        self.schedule(time, entity_key);
+        true
+    }
+
+    /// Same as [`Simulation::schedule`] but the event is tagged with `category`.
+    ///
+    /// Processed events are counted by category in [`Simulation::stats`], events
+    /// scheduled without a tag have category 0.
+    #[inline]
+    pub fn schedule_tagged(&mut self, time: Duration, entity_key: Key, category: u16) {
+        self.scheduler.schedule_tagged(time, entity_key, category)
+    }
+
+    /// Same as [`Simulation::schedule`] but among events at the same time, queue and
+    /// [priority](Simulation::set_priority) the ones with a lower `sort_key` run first,
+    /// like an earlier deadline. Events scheduled without one have a sort key of zero.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `sort_key` is NaN.
+    #[inline]
+    pub fn schedule_with_key(&mut self, time: Duration, entity_key: Key, sort_key: f64) {
+        self.scheduler.schedule_with_key(time, entity_key, sort_key)
+    }
+
+    /// Adds a new event queue named `name`, see [`Simulation::schedule_in_queue`].
+    ///
+    /// At equal times events of this queue run after the ones of every queue added before.
+    pub fn add_queue(&mut self, name: impl Into<String>) -> QueueId {
+        self.scheduler.add_queue(name)
+    }
+
+    /// Returns the id of the event queue named `name`.
+    #[must_use]
+    pub fn queue_id(&self, name: &str) -> Option<QueueId> {
+        self.scheduler.queue_id(name)
+    }
+
+    /// Schedules `entity_key` at `self.time() + time` in `queue`.
+    ///
+    /// All queues share the clock, events at the same time run in the order
+    /// the queues were added and then in the order they were scheduled.
+    ///
+    /// If `entity_key` was already scheduled in the same queue it will ignore the following calls
+    #[inline]
+    pub fn schedule_in_queue(&mut self, queue: QueueId, time: Duration, entity_key: Key) {
+        self.scheduler.schedule_in_queue(queue, time, entity_key)
+    }
+
     /// Schedules `entity_key` to be executed for at `self.time()`.
     ///
     /// the `entity_key` argument is a [`Key`] corresponding to the [Generator](crate::GenBoxed) to be scheduled.
@@ -62,6 +514,129 @@ where
         self.scheduler.schedule_now(entity_key)
     }
 
+    /// Returns a handle to the pool of buffers used for [`Action::ActivateMany`].
+    ///
+    /// Buffers taken from the pool are returned to it after the activations are
+    /// applied, so generators can yield `ActivateMany` without allocating every time.
+    #[must_use]
+    pub fn key_buffers(&self) -> KeyBufferPool {
+        self.key_buffers.clone()
+    }
+
+    /// Returns the keys of `set` that can be activated, ready for an `ActivateMany`.
+    ///
+    /// Only passive entities are kept, keys of entities that are active, completed or
+    /// were removed are skipped. The buffer comes from the [key buffer pool](Simulation::key_buffers).
+    #[must_use]
+    pub fn activate_set(&self, set: &KeySet) -> Vec<Key> {
+        let mut keys = self.key_buffers.take();
+        keys.extend(
+            set.iter()
+                .filter(|&key| self.entity_state(key) == Some(EntityState::Passive)),
+        );
+        keys
+    }
+
+    /// Create a [Group] of the entities in `keys` to activate or passivate them together.
+    pub fn create_group(&mut self, keys: impl IntoIterator<Item = Key>) -> GroupId {
+        self.groups.push(Group::new(keys));
+        GroupId(self.groups.len() - 1)
+    }
+
+    /// Returns the group identified by `id`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` wasn't created by this simulation.
+    #[must_use]
+    pub fn group(&self, id: GroupId) -> &Group {
+        self.groups
+            .get(id.0)
+            .unwrap_or_else(|| panic!("Unknown group {:?}", id))
+    }
+
+    /// Activate every passive member of the group `id` and schedule it now.
+    ///
+    /// Active members are left untouched and completed or removed ones are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` wasn't created by this simulation.
+    pub fn activate_group(&mut self, id: GroupId) {
+        let members = self.group(id).members().to_vec();
+        for key in members {
+            self.activate_passive(key);
+        }
+    }
+
+    /// Activate `key` and schedule it now if it is passive, returns whether it was.
+    fn activate_passive(&mut self, key: Key) -> bool {
+        if let Some(state @ EntityState::Passive) = self.entities.get_state_mut(key) {
+            *state = EntityState::Active;
+            self.scheduler.schedule_now_or_sooner(key);
+            self.entities.mark_activated(key);
+            self.state_changed(key, EntityState::Passive, EntityState::Active);
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Send `message` to `key` through `mailbox` and activate the receiver if it was
+    /// waiting for a message, see [Mailbox].
+    ///
+    /// A receiver that is still running takes the message the next time it checks its mailbox.
+    pub fn send<T>(&mut self, mailbox: &mut Mailbox<T>, key: Key, message: T) {
+        if let Some(receiver) = mailbox.send(key, message) {
+            self.activate_passive(receiver);
+        }
+    }
+
+    /// Passivate every active member of the group `id`, cancelling their pending events.
+    ///
+    /// Like after a [`Action::Cancel`] the members resume from where they yielded once
+    /// activated again. Completed or removed members are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `id` wasn't created by this simulation.
+    pub fn passivate_group(&mut self, id: GroupId) {
+        let members = self.group(id).members().to_vec();
+        for key in members {
+            if let Some(state @ EntityState::Active) = self.entities.get_state_mut(key) {
+                *state = EntityState::Passive;
+                if self.scheduler.remove(key) {
+                    self.entities.mark_interrupted(key);
+                }
+                self.state_changed(key, EntityState::Active, EntityState::Passive);
+            }
+        }
+    }
+
+    /// Bring the simulation to a quiet state: every pending event is cancelled and every
+    /// active entity is passivated, nothing is removed.
+    ///
+    /// Returns the keys of the entities that were active. Like after a [`Action::Cancel`]
+    /// they resume from where they yielded once activated again, their holds are lost.
+    pub fn quiesce(&mut self) -> Vec<Key> {
+        let active: Vec<Key> = self
+            .entities
+            .keys()
+            .filter(|&key| self.entities.get_state(key) == Some(&EntityState::Active))
+            .collect();
+        for &key in &active {
+            if let Some(state) = self.entities.get_state_mut(key) {
+                *state = EntityState::Passive;
+            }
+            if self.scheduler.next_time_for(key).is_some() {
+                self.entities.mark_interrupted(key);
+            }
+            self.state_changed(key, EntityState::Active, EntityState::Passive);
+        }
+        self.scheduler.clear();
+        active
+    }
+
     /// Returns the current simulation time.
     #[must_use]
     #[inline]
@@ -69,163 +644,2996 @@ where
         self.scheduler.time()
     }
 
+    /// Returns the current simulation time in seconds.
+    #[must_use]
+    #[inline]
+    pub fn now_secs(&self) -> f64 {
+        self.time().as_secs_f64()
+    }
+
+    /// Returns a handle with read-only access to the simulation time.
     #[must_use]
     #[inline]
     pub fn clock(&self) -> crate::scheduler::ClockRef {
         self.scheduler.clock()
     }
 
+    /// Returns the counters collected while running the simulation.
+    #[must_use]
+    pub fn stats(&self) -> &SimulationStats {
+        &self.stats
+    }
+
+    /// Returns the scheduled events sorted by ascending time.
+    #[must_use]
+    pub fn dump_queue(&self) -> Vec<(Duration, Key)> {
+        self.scheduler.pending()
+    }
+
+    /// Same as [`Simulation::dump_queue`] with the order in which each event was scheduled,
+    /// counting from zero, to compare the insertion and processing orders.
+    #[must_use]
+    pub fn dump_queue_sequenced(&self) -> Vec<(Duration, Key, u64)> {
+        self.scheduler.pending_sequenced()
+    }
+
+    /// Returns the time of the next event or `None` if none are scheduled.
+    #[must_use]
+    pub fn peek_time(&self) -> Option<Duration> {
+        self.scheduler.peek().map(|(time, _)| time)
+    }
+
+    /// Returns how long the simulation stays idle before the next event, `None` if none
+    /// are scheduled.
+    ///
+    /// A driver pacing the simulation itself can sleep for the gap, scaled by its speed,
+    /// in one go instead of polling, and logging drivers can report the skipped interval.
+    /// Scheduling another event meanwhile can only shorten the gap.
+    #[must_use]
+    pub fn next_gap(&self) -> Option<Duration> {
+        self.peek_time()
+            .map(|time| time.saturating_sub(self.time()))
+    }
+
+    /// Returns `true` if there are events left to process.
+    #[must_use]
+    pub fn has_pending(&self) -> bool {
+        !self.scheduler.is_empty()
+    }
+
+    /// Returns `true` if there are no events left to process.
+    #[must_use]
+    pub fn is_idle(&self) -> bool {
+        self.scheduler.is_empty()
+    }
+
+    /// Skip scheduling an entity that already has an event at the identical time, off
+    /// by default.
+    ///
+    /// An entity signalled several times in the same instant is then resumed once, so a
+    /// model expecting one resume per signal has to count the signals itself.
+    pub fn set_coalesce_same_time(&mut self, coalesce: bool) {
+        self.scheduler.set_coalesce_same_time(coalesce);
+    }
+
+    /// Returns how many schedule calls were skipped because the entity already had an
+    /// event at the identical time, see [`Simulation::set_coalesce_same_time`].
+    #[must_use]
+    pub fn coalesced_events(&self) -> u64 {
+        self.scheduler.coalesced()
+    }
+
+    /// Returns the next `n` scheduled events in the order they will be processed.
+    #[must_use]
+    pub fn upcoming(&self, n: usize) -> Vec<(Duration, Key)> {
+        self.scheduler.peek_n(n)
+    }
+
     /// Retrieve a copy of the current [EntityState] of the generator asociated with `key`
     #[must_use]
     pub fn entity_state(&self, key: Key) -> Option<EntityState> {
         self.entities.get_state(key).copied()
     }
 
-    /// Advance the simulation one event.
-    pub fn step_with(&mut self, resume_with: R) -> ShouldContinue {
-        if let Some(event_entry) = self.scheduler.pop() {
-            let key = event_entry.key();
+    /// Returns why the entity associated with `key` was resumed last, `None` if it wasn't
+    /// resumed yet or was removed.
+    #[must_use]
+    pub fn last_wake_reason(&self, key: Key) -> Option<WakeReason> {
+        self.entities.last_wake(key)
+    }
 
-            let state = self.entities.step_with(key, resume_with);
-            match state {
-                CoroutineState::Yielded(action) => {
-                    let entity_state = self.entities.get_state_mut(key).unwrap();
-                    match action {
-                        Action::Hold(duration) => {
-                            // TODO: Maybe remove this check. It shouldn't happen.
-                            if let EntityState::Passive = *entity_state {
-                                panic!(
-                                    "A passive entity received a hold command. ID = {}",
-                                    key.id
-                                );
-                            }
-                            self.schedule(duration, key);
-                        }
-                        Action::Passivate => {
-                            // TODO: This check shouldn't happen, a passive generator
-                            // shouldn't be able to send another passivate
-                            match *entity_state {
-                                EntityState::Active => {
-                                    *entity_state = EntityState::Passive;
-                                }
-                                EntityState::Passive => {
-                                    panic!(
-                                        "A passive entity received a passivate command. ID = {}",
-                                        key.id
-                                    );
-                                }
-                            }
-                        }
-                        Action::ActivateOne(other_key) => {
-                            // TODO: This check shouldn't be necessary a passive generator
-                            // shouldn't be able to send an activate.
-                            if let EntityState::Passive = *entity_state {
-                                panic!("A passive entity sended an activate. ID = {}", key.id);
-                            }
-                            self.schedule_now(key);
-
-                            let other_state = self.entities.get_state_mut(other_key).unwrap();
-                            match *other_state {
-                                EntityState::Passive => {
-                                    *other_state = EntityState::Active;
-                                }
-                                EntityState::Active => {
-                                    panic!(
-                                        "Entity ID = {} tried to Activate Entity ID = {} but it was already active",
-                                        key.id,
-                                        other_key.id
-                                    )
-                                }
-                            }
-
-                            self.schedule_now(other_key);
-                        }
-                        Action::ActivateMany(other_keys) => {
-                            if let EntityState::Passive = *entity_state {
-                                panic!("A passive entity sended an activate. ID = {}", key.id);
-                            }
-                            self.schedule_now(key);
-                            for other_key in other_keys {
-                                let other_state = self.entities.get_state_mut(other_key).unwrap();
-                                match *other_state {
-                                    EntityState::Passive => {
-                                        *other_state = EntityState::Active;
-                                    }
-                                    EntityState::Active => {
-                                        panic!(
-                                            "Entity ID = {} tried to Activate Entity ID = {} but it was already active",
-                                            key.id,
-                                            other_key.id
-                                        )
-                                    }
-                                }
-                                self.schedule_now(other_key);
-                            }
-                        }
-                        Action::Cancel(other_key) => {
-                            if let EntityState::Passive = *entity_state {
-                                panic!(
-                                    "A passive entity did a Cancel. ID = {} to ID = {}",
-                                    key.id, other_key.id
-                                );
-                            }
-                            self.schedule_now(key);
-                            
-                            // -----------------------------------
-                            let other_state = self.entities.get_state_mut(other_key).unwrap();
-                            match *other_state {
-                                EntityState::Active => {
-                                    *other_state = EntityState::Passive;
-                                }
-                                EntityState::Passive => {
-                                    panic!(
-                                        "Entity ID = {} sent Cancel to Entity ID = {} but is was in a passive state",
-                                        key.id,
-                                        other_key.id
-                                    )
-                                }
-                            }
-                            // TODO: PROFILE AND OPTIMIZE THIS ENTIRE CHUNK
-
-                            // TODO: Maybe remove this check because if it passed the previous check then an event is guaranteed to exist in the scheduler
-                            // ---------------
-                            if !self.scheduler.remove(other_key) {
-                                panic!("Entity ID = {} send Cancel to ID = {} and it wasn't scheduled", key.id, other_key.id);
-                            };
-                            // ---------------
-                        }
-                    }
-                }
-                CoroutineState::Complete(_) => {
-                    self.entities.remove(key);
-                }
-            }
-            ShouldContinue::Advance
-        } else {
-            ShouldContinue::Break
-        }
+    /// Returns how many times the entity associated with `key` has been resumed.
+    ///
+    /// Returns `None` if the entity was removed or reaped.
+    #[must_use]
+    pub fn component_steps(&self, key: Key) -> Option<u64> {
+        self.entities.steps(key)
     }
 
-    pub fn state(&self) -> Rc<Cell<State>> {
-        Rc::clone(&self.state)
+    /// Returns an iterator over the keys of the entities that haven't completed
+    /// nor been removed from the simulation.
+    pub fn live_keys(&self) -> impl Iterator<Item = Key> + '_ {
+        self.entities.keys()
     }
-}
 
-impl Simulation<()> {
-    #[inline]
-    pub fn step(&mut self) -> ShouldContinue {
-        self.step_with(())
+    /// Returns the keys of the passive entities without a pending event, in ascending order.
+    ///
+    /// After a run ends these can only resume if something outside the simulation
+    /// activates them, usually the sign of a deadlock like a consumer waiting on an
+    /// empty store whose producers are gone.
+    #[must_use]
+    pub fn stuck_components(&self) -> Vec<Key> {
+        self.entities
+            .keys()
+            .filter(|&key| {
+                self.entity_state(key) == Some(EntityState::Passive)
+                    && self.scheduler.next_time_for(key).is_none()
+            })
+            .collect()
     }
 
-    pub fn run_until_empty(&mut self) {
-        while let ShouldContinue::Advance = self.step() {}
+    /// Take the value returned by the generator of `key` when it completed.
+    ///
+    /// Returns `None` if the entity hasn't completed, was reaped or its value was already taken.
+    pub fn take_result(&mut self, key: Key) -> Option<O> {
+        self.entities.take_output(key)
     }
 
-    pub fn run_with_limit(&mut self, limit: Duration) {
-        while let ShouldContinue::Advance = self.step() {
-            if self.time() >= limit {
-                break;
-            }
+    /// Returns the keys of the entities that completed, in ascending order.
+    ///
+    /// Reaped entities are no longer reported.
+    #[must_use]
+    pub fn completed_keys(&self) -> Vec<Key> {
+        self.entities.completed_keys().collect()
+    }
+
+    /// Free the slots of the entities that completed, returning their keys and the
+    /// time at which they completed in ascending key order.
+    ///
+    /// Freed slots are reused by the next added entities, which get the same keys, so
+    /// the keys of reaped entities shouldn't be kept around. Their pending events,
+    /// watchers and breakpoints are dropped.
+    pub fn drain_completed(&mut self) -> Vec<(Key, Duration)> {
+        let drained = self.entities.drain_completed();
+        for &(key, _) in &drained {
+            // Completed entities are never scheduled again, this is only defensive.
+            self.scheduler.remove(key);
+            self.scheduler.set_priority(key, 0);
+            self.watchers.remove(&key);
+            self.breakpoints.remove(&key);
         }
+        drained
+    }
+
+    /// Free the slots of the entities that completed, returning how many were freed.
+    ///
+    /// Completed entities report [`EntityState::Completed`] until they are reaped,
+    /// afterwards [`Simulation::entity_state`] returns `None` for them. As with
+    /// [`Simulation::drain_completed`] their keys are reused by the next added entities.
+    pub fn reap_completed(&mut self) -> usize {
+        self.drain_completed().len()
+    }
+
+    /// Count the entities in the simulation by their [EntityState].
+    ///
+    /// Entities that completed their execution are not counted.
+    #[must_use]
+    pub fn state_counts(&self) -> StateCounts {
+        self.entities.state_counts()
+    }
+
+    /// Start recording which entities activate which.
+    ///
+    /// Recording is off by default, see [`Simulation::export_dot`].
+    pub fn record_activations(&mut self) {
+        self.activation_graph
+            .get_or_insert_with(ActivationGraph::default);
+    }
+
+    /// Returns the activations recorded so far or `None` if recording wasn't enabled.
+    #[must_use]
+    pub fn activation_graph(&self) -> Option<&ActivationGraph> {
+        self.activation_graph.as_ref()
+    }
+
+    /// Export the recorded activations as a GraphViz DOT graph.
+    ///
+    /// Every entity that wasn't reaped is a node, as is every entity with a recorded
+    /// activation. If recording wasn't enabled with [`Simulation::record_activations`]
+    /// the graph has no edges.
+    #[must_use]
+    pub fn export_dot(&self) -> String {
+        let mut nodes: BTreeSet<Key> = self.entities.keys().collect();
+        nodes.extend(self.entities.completed_keys());
+        match &self.activation_graph {
+            Some(graph) => {
+                nodes.extend(graph.keys());
+                graph.to_dot(nodes)
+            }
+            None => ActivationGraph::default().to_dot(nodes),
+        }
+    }
+
+    /// Start recording the `(time, key)` of every processed event.
+    ///
+    /// Recording is off by default, see [`Simulation::replay_recorder`].
+    pub fn record_replay(&mut self) {
+        self.replay.get_or_insert_with(ReplayRecorder::default);
+    }
+
+    /// Set a hook called when a step finds no events left, replacing the previous one.
+    ///
+    /// The hook may schedule new events, in which case the step reports
+    /// [`ShouldContinue::Advance`] and the run loops keep going. A hook that schedules
+    /// nothing ends the run, so it is called at most once in a row without work being added.
+    pub fn set_on_idle(&mut self, on_idle: IdleHook<R, O>) {
+        self.on_idle = Some(on_idle);
+    }
+
+    /// Call `callback` every `interval`, starting `interval` from now, until it is
+    /// cancelled with [`Simulation::cancel_recurring`].
+    ///
+    /// The callback is driven by a timer entity that holds for `interval` in a loop, so
+    /// it shows up among the live entities and a run only ends once it is cancelled.
+    /// The callback runs right after the timer's event is processed and may cancel itself.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `interval` is zero.
+    pub fn schedule_recurring(
+        &mut self,
+        interval: Duration,
+        callback: RecurringHook<R, O>,
+    ) -> RecurringId {
+        assert!(
+            !interval.is_zero(),
+            "The interval must be greater than zero"
+        );
+        let key = self.add_generator(Box::new(
+            #[coroutine]
+            move |_| loop {
+                yield Action::Hold(interval);
+            },
+        ));
+        self.schedule(interval, key);
+        self.recurring.insert(key, callback);
+        RecurringId(key)
+    }
+
+    /// Stop calling a callback added with [`Simulation::schedule_recurring`] and remove
+    /// its timer entity, returns whether it was still recurring.
+    pub fn cancel_recurring(&mut self, id: RecurringId) -> bool {
+        self.recurring.remove(&id.0);
+        self.detach(id.0).is_some()
+    }
+
+    /// Add a callback called with the time and [Key] of every processed event,
+    /// before the entity is resumed.
+    pub fn add_step_observer(&mut self, observer: impl FnMut(Duration, Key) + 'static) {
+        self.step_observers.push(Box::new(observer));
+    }
+
+    /// Start recording the state changes of the entities, see [StateTimeline].
+    ///
+    /// Recording is off by default.
+    pub fn record_timeline(&mut self) {
+        self.timeline.get_or_insert_with(StateTimeline::default);
+    }
+
+    /// Returns the state changes recorded so far or `None` if recording wasn't enabled.
+    #[must_use]
+    pub fn timeline(&self) -> Option<&StateTimeline> {
+        self.timeline.as_ref()
+    }
+
+    /// Start measuring the wall-clock time spent processing each event, see [EngineProfiler].
+    ///
+    /// Profiling is off by default to avoid reading the system clock on every step.
+    pub fn record_profile(&mut self) {
+        self.profiler.get_or_insert_with(EngineProfiler::default);
+    }
+
+    /// Returns the measurements taken so far or `None` if profiling wasn't enabled.
+    #[must_use]
+    pub fn profiler(&self) -> Option<&EngineProfiler> {
+        self.profiler.as_ref()
+    }
+
+    /// Record the sojourn time of every entity added from now on into `recorder`,
+    /// from the time it is added until it completes.
+    ///
+    /// Replaces the recorder in use, if any. Entities added before keep no entry time
+    /// unless one was [set](SojournRecorder::enter) on `recorder`.
+    pub fn record_sojourns(&mut self, recorder: SojournRecorder) {
+        self.sojourns = Some(recorder);
+    }
+
+    /// Returns the sojourn times recorded so far or `None` if recording wasn't enabled.
+    #[must_use]
+    pub fn sojourns(&self) -> Option<&SojournRecorder> {
+        self.sojourns.as_ref()
+    }
+
+    /// Returns the events recorded so far or `None` if recording wasn't enabled.
+    #[must_use]
+    pub fn replay_recorder(&self) -> Option<&ReplayRecorder> {
+        self.replay.as_ref()
+    }
+
+    /// Stop before processing the events of `key`.
+    ///
+    /// Stepping returns [`ShouldContinue::Breakpoint`] instead of processing the event,
+    /// the next step processes it.
+    pub fn set_breakpoint(&mut self, key: Key) {
+        self.breakpoints.insert(key);
+    }
+
+    /// Remove the breakpoint of `key`, returns `false` if it didn't have one.
+    pub fn clear_breakpoint(&mut self, key: Key) -> bool {
+        self.breakpoints.remove(&key)
+    }
+
+    /// Call `watcher` with the key, the old and the new state every time a step
+    /// changes the state of the entity associated with `key`.
+    pub fn watch(&mut self, key: Key, watcher: Watcher) {
+        self.watchers.entry(key).or_default().push(watcher);
+    }
+
+    fn state_changed(&mut self, key: Key, old: EntityState, new: EntityState) {
+        if let Some(timeline) = &mut self.timeline {
+            timeline.record(key, self.scheduler.time(), new);
+        }
+        if let Some(watchers) = self.watchers.get_mut(&key) {
+            for watcher in watchers {
+                watcher(key, old, new);
+            }
+        }
+    }
+
+    /// Advance the simulation one event.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the processed event fails with a [StepError].
+    pub fn step_with(&mut self, resume_with: R) -> ShouldContinue {
+        self.try_step_with(resume_with)
+            .unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    /// Same as [`Simulation::step_with`] but also describes the processed event, `None`
+    /// if no event was processed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the processed event fails with a [StepError].
+    pub fn step_with_info(&mut self, resume_with: R) -> (ShouldContinue, Option<StepInfo>) {
+        self.last_step = None;
+        let should_continue = self.step_with(resume_with);
+        (should_continue, self.last_step.take())
+    }
+
+    /// Advance the simulation one event, returning an error instead of panicking
+    /// when the yielded action can't be applied.
+    ///
+    /// # Errors
+    ///
+    /// Returns a [StepError] if an `ActivateOne` or `ActivateMany` targets a missing,
+    /// completed or already active entity or a `Cancel` targets a missing or completed one.
+    /// None of the targets are changed in that case and the caller isn't rescheduled, it
+    /// stays active without a pending event.
+    ///
+    /// With the `catch-panics` feature a generator that panics is removed and reported as
+    /// [`StepError::ComponentPanicked`], the simulation can keep stepping the other entities.
+    ///
+    /// An event whose entity completed or was removed is skipped and counted in
+    /// [`SimulationStats::stale_events`], the step still returns [`ShouldContinue::Advance`]
+    /// and the clock moves to the event's time.
+    pub fn try_step_with(&mut self, resume_with: R) -> Result<ShouldContinue, StepError> {
+        if !self.debug_checks {
+            return self.profile_next(resume_with);
+        }
+        let before = self.scheduler.time();
+        let result = self.profile_next(resume_with);
+        if let Some(violation) = self.find_violation(before) {
+            panic!("Invariant violated: {}", violation);
+        }
+        result
+    }
+
+    fn find_violation(&self, before: Duration) -> Option<String> {
+        let now = self.scheduler.time();
+        if now < before {
+            return Some(format!(
+                "the clock went back from {:?} to {:?}",
+                before, now
+            ));
+        }
+        if let Some(inconsistency) = self.entities.find_inconsistency() {
+            return Some(inconsistency);
+        }
+        self.scheduler
+            .pending()
+            .into_iter()
+            .find(|&(_, key)| self.entities.get_state(key).is_none())
+            .map(|(time, key)| {
+                format!(
+                    "Entity ID = {} has an event pending at {:?} but was removed",
+                    key.id, time
+                )
+            })
+    }
+
+    fn profile_next(&mut self, resume_with: R) -> Result<ShouldContinue, StepError> {
+        if self.profiler.is_none() {
+            return self.process_next(resume_with);
+        }
+        let processed = self.stats.events_processed;
+        let started = Instant::now();
+        let result = self.process_next(resume_with);
+        let elapsed = started.elapsed();
+        if let Some(profiler) = &mut self.profiler {
+            if self.stats.events_processed > processed {
+                profiler.record(elapsed);
+            }
+        }
+        result
+    }
+
+    fn process_next(&mut self, resume_with: R) -> Result<ShouldContinue, StepError> {
+        // Taken even without breakpoints, the last one may have been cleared meanwhile.
+        let resuming = self.resume_from_breakpoint.take();
+        if !self.breakpoints.is_empty() {
+            if let Some((_, next)) = self.scheduler.peek() {
+                if resuming != Some(next) && self.breakpoints.contains(&next) {
+                    self.resume_from_breakpoint = Some(next);
+                    return Ok(ShouldContinue::Breakpoint(next));
+                }
+            }
+        }
+        if let Some(event_entry) = self.scheduler.pop() {
+            let key = event_entry.key();
+            if matches!(
+                self.entities.get_state(key),
+                None | Some(EntityState::Completed)
+            ) {
+                // Nothing left to resume, the event outlived its entity. The clock
+                // already moved to its time.
+                self.stats.stale_events += 1;
+                return Ok(ShouldContinue::Advance);
+            }
+            self.stats
+                .record_event(self.scheduler.time(), event_entry.category());
+            if let Some(replay) = &mut self.replay {
+                replay.record(self.scheduler.time(), key);
+            }
+            for observer in &mut self.step_observers {
+                observer(self.scheduler.time(), key);
+            }
+            // Only an event scheduled for it by someone else, like an `Action::ScheduleOther`,
+            // resumes a passive entity. It's running from now on.
+            if let Some(state @ EntityState::Passive) = self.entities.get_state_mut(key) {
+                *state = EntityState::Active;
+                self.state_changed(key, EntityState::Passive, EntityState::Active);
+            }
+
+            #[cfg(not(feature = "catch-panics"))]
+            let state = self.entities.step_with(key, resume_with);
+            #[cfg(feature = "catch-panics")]
+            let state = match self.entities.catch_step_with(key, resume_with) {
+                Ok(state) => state,
+                Err(_) => {
+                    self.scheduler.remove(key);
+                    return Err(StepError::ComponentPanicked(key));
+                }
+            };
+            match state {
+                CoroutineState::Yielded(action) => {
+                    self.record_step(key, action.kind());
+                    self.apply_action(key, action, true)?;
+                    if let Some(mut callback) = self.recurring.remove(&key) {
+                        callback(self);
+                        // Unless it cancelled itself.
+                        if self.entities.get_state(key).is_some() {
+                            self.recurring.insert(key, callback);
+                        }
+                    }
+                }
+                CoroutineState::Complete(output) => {
+                    self.record_step(key, ActionKind::Completed);
+                    self.entities.complete(key, self.scheduler.time(), output);
+                    if let Some(sojourns) = &mut self.sojourns {
+                        sojourns.complete(key, self.scheduler.time());
+                    }
+                    self.state_changed(key, EntityState::Active, EntityState::Completed);
+                }
+            }
+            Ok(ShouldContinue::Advance)
+        } else if let Some(mut on_idle) = self.on_idle.take() {
+            on_idle(self);
+            // Keep the hook unless it replaced itself.
+            self.on_idle.get_or_insert(on_idle);
+            if self.has_pending() {
+                Ok(ShouldContinue::Advance)
+            } else {
+                Ok(ShouldContinue::Break)
+            }
+        } else {
+            Ok(ShouldContinue::Break)
+        }
+    }
+
+    /// Remember the processed event for [`Simulation::step_with_info`].
+    fn record_step(&mut self, key: Key, action_kind: ActionKind) {
+        self.last_step = Some(StepInfo {
+            key,
+            time: self.scheduler.time(),
+            action_kind,
+        });
+    }
+
+    /// Apply the `action` yielded by the entity `key`.
+    ///
+    /// Activating or cancelling other entities resumes the caller now unless
+    /// `reschedule_caller` is `false`, as done for the actions inside an [`Action::Multi`].
+    fn apply_action(
+        &mut self,
+        key: Key,
+        action: Action,
+        reschedule_caller: bool,
+    ) -> Result<(), StepError> {
+        let entity_state = self
+            .entities
+            .get_state_mut(key)
+            .ok_or(StepError::MissingComponent(key))?;
+        match action {
+            Action::Hold(duration) => {
+                // TODO: Maybe remove this check. It shouldn't happen.
+                if let EntityState::Passive = *entity_state {
+                    panic!("A passive entity received a hold command. ID = {}", key.id);
+                }
+                self.stats.record_hold(duration);
+                self.schedule(duration, key);
+            }
+            Action::Passivate => {
+                // TODO: This check shouldn't happen, a passive generator
+                // shouldn't be able to send another passivate
+                match *entity_state {
+                    EntityState::Active => {
+                        *entity_state = EntityState::Passive;
+                        self.state_changed(key, EntityState::Active, EntityState::Passive);
+                    }
+                    EntityState::Passive => {
+                        panic!(
+                            "A passive entity received a passivate command. ID = {}",
+                            key.id
+                        );
+                    }
+                    EntityState::Completed => {
+                        unreachable!("Entity ID = {} yielded after completing", key.id)
+                    }
+                }
+            }
+            Action::ActivateOne(other_key) => {
+                // TODO: This check shouldn't be necessary a passive generator
+                // shouldn't be able to send an activate.
+                if let EntityState::Passive = *entity_state {
+                    panic!("A passive entity sended an activate. ID = {}", key.id);
+                }
+                match self.entities.get_state_mut(other_key) {
+                    Some(other_state @ EntityState::Passive) => {
+                        *other_state = EntityState::Active;
+                        self.state_changed(other_key, EntityState::Passive, EntityState::Active);
+                    }
+                    Some(EntityState::Active) => {
+                        return Err(StepError::AlreadyActive {
+                            activator: key,
+                            target: other_key,
+                        });
+                    }
+                    Some(EntityState::Completed) | None => {
+                        return Err(StepError::MissingComponent(other_key));
+                    }
+                }
+                // The caller stays active and this is its only event, without it
+                // the caller would never be resumed again.
+                if reschedule_caller {
+                    self.schedule_now(key);
+                }
+                // A later event of the target, like from an `Action::ScheduleOther`,
+                // would swallow the activation.
+                self.scheduler.schedule_now_or_sooner(other_key);
+                self.entities.mark_activated(other_key);
+                if let Some(graph) = &mut self.activation_graph {
+                    graph.record(key, other_key);
+                }
+            }
+            Action::ActivateMany(other_keys) => {
+                if let EntityState::Passive = *entity_state {
+                    panic!("A passive entity sended an activate. ID = {}", key.id);
+                }
+                let result = self.activate_targets(key, &other_keys, reschedule_caller);
+                self.key_buffers.give_back(other_keys);
+                result?;
+            }
+            Action::ActivateSmall(other_keys) => {
+                if let EntityState::Passive = *entity_state {
+                    panic!("A passive entity sended an activate. ID = {}", key.id);
+                }
+                let mut targets = [key; 4];
+                let mut len = 0;
+                for other_key in other_keys.into_iter().flatten() {
+                    targets[len] = other_key;
+                    len += 1;
+                }
+                self.activate_targets(key, &targets[..len], reschedule_caller)?;
+            }
+            Action::Cancel(other_key) => {
+                if let EntityState::Passive = *entity_state {
+                    panic!(
+                        "A passive entity did a Cancel. ID = {} to ID = {}",
+                        key.id, other_key.id
+                    );
+                }
+                // A target removed or completed earlier in the same instant is an error
+                // for the caller, which isn't rescheduled then.
+                let other_state = match self.entities.get_state_mut(other_key) {
+                    Some(EntityState::Completed) | None => {
+                        return Err(StepError::MissingComponent(other_key));
+                    }
+                    Some(other_state) => other_state,
+                };
+                match *other_state {
+                    EntityState::Active => {
+                        *other_state = EntityState::Passive;
+                        self.state_changed(other_key, EntityState::Active, EntityState::Passive);
+                    }
+                    EntityState::Passive => {
+                        panic!(
+                            "Entity ID = {} sent Cancel to Entity ID = {} but is was in a passive state",
+                            key.id,
+                            other_key.id
+                        )
+                    }
+                    EntityState::Completed => unreachable!(),
+                }
+                if reschedule_caller {
+                    self.schedule_now(key);
+                }
+                // TODO: PROFILE AND OPTIMIZE THIS ENTIRE CHUNK
+
+                // TODO: Maybe remove this check because if it passed the previous check then an event is guaranteed to exist in the scheduler
+                // ---------------
+                if !self.scheduler.remove(other_key) {
+                    panic!(
+                        "Entity ID = {} send Cancel to ID = {} and it wasn't scheduled",
+                        key.id, other_key.id
+                    );
+                };
+                self.entities.mark_interrupted(other_key);
+                // ---------------
+            }
+            Action::ScheduleOther(other_key, duration) => {
+                if let Some(EntityState::Completed) | None = self.entities.get_state(other_key) {
+                    return Err(StepError::MissingComponent(other_key));
+                }
+                self.schedule(duration, other_key);
+                if reschedule_caller {
+                    self.schedule_now(key);
+                }
+            }
+            Action::HoldCapped(duration, cap) => {
+                let until = self.time().saturating_add(duration).min(cap);
+                self.apply_action(key, Action::Hold(until.saturating_sub(self.time())), true)?;
+            }
+            Action::ActivateManyThenHold(other_keys, duration) => {
+                self.apply_action(key, Action::ActivateMany(other_keys), false)?;
+                self.apply_action(key, Action::Hold(duration), reschedule_caller)?;
+            }
+            Action::Multi(actions) => {
+                // The caller's own action is applied last so the effects on others
+                // happen while it's still active, whatever the order in the Vec.
+                let (mut own, others): (Vec<_>, Vec<_>) = actions.into_iter().partition(|action| {
+                    matches!(
+                        action,
+                        Action::Hold(_)
+                            | Action::HoldCapped(..)
+                            | Action::Passivate
+                            | Action::ActivateManyThenHold(..)
+                    )
+                });
+                let nested = others
+                    .iter()
+                    .any(|action| matches!(action, Action::Multi(_)));
+                if own.len() > 1 || nested {
+                    return Err(StepError::InvalidMulti(key));
+                }
+                for action in others {
+                    self.apply_action(key, action, false)?;
+                }
+                match own.pop() {
+                    Some(action) => self.apply_action(key, action, true)?,
+                    None => self.schedule_now(key),
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Activate every passive entity in `targets` for `ActivateMany` and `ActivateSmall`.
+    fn activate_targets(
+        &mut self,
+        key: Key,
+        targets: &[Key],
+        reschedule_caller: bool,
+    ) -> Result<(), StepError> {
+        // Every target is checked before any of them is touched so an
+        // invalid key doesn't leave the earlier ones activated.
+        for &other_key in targets {
+            match self.entities.get_state(other_key) {
+                Some(EntityState::Passive) => {}
+                Some(EntityState::Active) => {
+                    return Err(StepError::AlreadyActive {
+                        activator: key,
+                        target: other_key,
+                    });
+                }
+                Some(EntityState::Completed) | None => {
+                    return Err(StepError::MissingComponent(other_key));
+                }
+            }
+        }
+        // Same as ActivateOne, the caller is rescheduled once, not per target.
+        if reschedule_caller {
+            self.schedule_now(key);
+        }
+        for &other_key in targets {
+            let other_state = self
+                .entities
+                .get_state_mut(other_key)
+                .ok_or(StepError::MissingComponent(other_key))?;
+            // Every target was passive, an active one is listed twice
+            // and was already activated.
+            if *other_state == EntityState::Active {
+                continue;
+            }
+            *other_state = EntityState::Active;
+            self.state_changed(other_key, EntityState::Passive, EntityState::Active);
+            self.scheduler.schedule_now_or_sooner(other_key);
+            self.entities.mark_activated(other_key);
+            if let Some(graph) = &mut self.activation_graph {
+                graph.record(key, other_key);
+            }
+        }
+        Ok(())
+    }
+
+    /// Process up to `chunk` events and return, so a caller like an animation loop can
+    /// do other work between calls and continue where the previous one stopped.
+    ///
+    /// `resume` provides the value each generator is resumed with. The run also stops
+    /// early when no events are left, see [`RunOutcome::drained`], or at a breakpoint.
+    pub fn run_chunked(&mut self, chunk: usize, mut resume: impl FnMut() -> R) -> RunOutcome {
+        let before = self.stats.events_processed;
+        let mut breakpoint = None;
+        for _ in 0..chunk {
+            match self.step_with(resume()) {
+                ShouldContinue::Advance => {}
+                ShouldContinue::Break => break,
+                ShouldContinue::Breakpoint(key) => {
+                    breakpoint = Some(key);
+                    break;
+                }
+            }
+        }
+        RunOutcome {
+            processed: (self.stats.events_processed - before) as usize,
+            remaining: self.scheduler.len(),
+            stopped_at: self.time(),
+            breakpoint,
+        }
+    }
+
+    /// Advance the simulation until no events are left or `done` returns `true`.
+    ///
+    /// `resume` provides the value each generator is resumed with and `done` is
+    /// checked after every step with read-only access to the simulation.
+    ///
+    /// Returns [`ShouldContinue::Advance`] if `done` returned `true`,
+    /// [`ShouldContinue::Break`] once no events are left and [`ShouldContinue::Breakpoint`]
+    /// at a [breakpoint](Simulation::set_breakpoint), calling it again continues the run.
+    pub fn run_until_predicate<F>(
+        &mut self,
+        mut resume: impl FnMut() -> R,
+        mut done: F,
+    ) -> ShouldContinue
+    where
+        F: FnMut(&Simulation<R, O>) -> bool,
+    {
+        loop {
+            match self.step_with(resume()) {
+                ShouldContinue::Advance => {
+                    if done(self) {
+                        return ShouldContinue::Advance;
+                    }
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Clear the pending events, set the clock back to its start time and every entity
+    /// that hasn't completed back to [`EntityState::Active`]. Watchers and the timeline
+    /// see those changes, a pending breakpoint resume is dropped.
+    ///
+    /// Generators can't be rewound, an entity continues from where it yielded last
+    /// once it is scheduled again. Rebuilding the entities, for example with a
+    /// [ScenarioTemplate](crate::scenario::ScenarioTemplate), is the way to rerun a model
+    /// from the start. Queues, statistics and recordings are kept.
+    pub fn reset(&mut self) {
+        self.scheduler.reset();
+        self.resume_from_breakpoint = None;
+        let keys: Vec<Key> = self.entities.keys().collect();
+        for key in keys {
+            if let Some(state) = self.entities.get_state_mut(key) {
+                let old = *state;
+                if old != EntityState::Active {
+                    *state = EntityState::Active;
+                    self.state_changed(key, old, EntityState::Active);
+                }
+            }
+        }
+    }
+
+    /// Set how many simulated seconds [`Simulation::run_real_time`] advances per
+    /// wall-clock second, `1.0` by default.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `speed_factor` isn't finite and greater than zero.
+    pub fn set_speed_factor(&mut self, speed_factor: f64) {
+        assert!(
+            speed_factor.is_finite() && speed_factor > 0.0,
+            "speed factor must be finite and greater than zero, got {}",
+            speed_factor
+        );
+        self.speed_factor = speed_factor;
+    }
+
+    /// Advance the simulation until no events are left, never faster than real time.
+    ///
+    /// Before each event the thread sleeps until as much wall-clock time has passed
+    /// since the previous event as simulation time separates them. When processing
+    /// falls behind the next event runs right away, the lost time isn't recovered.
+    ///
+    /// See [`Simulation::set_speed_factor`] to run faster or slower than real time.
+    /// [Breakpoints](Simulation::set_breakpoint) are ignored, pausing would break the pacing.
+    pub fn run_real_time(&mut self, resume: impl FnMut() -> R) {
+        self.run_real_time_with(&mut SystemClock::new(), resume);
+    }
+
+    /// Same as [`Simulation::run_real_time`] with the wall-clock time read from `clock`,
+    /// a [MockClock](crate::MockClock) makes the run deterministic and instant.
+    pub fn run_real_time_with(
+        &mut self,
+        clock: &mut impl WallClock,
+        mut resume: impl FnMut() -> R,
+    ) {
+        let mut pacer = Pacer::new(clock.now(), self.time(), self.speed_factor);
+        loop {
+            if let Some((next, _)) = self.scheduler.peek() {
+                let delay = pacer.delay(clock.now(), next);
+                if !delay.is_zero() {
+                    clock.sleep(delay);
+                }
+                pacer.mark(clock.now(), next);
+            }
+            let mut outcome = self.step_with(resume());
+            if let ShouldContinue::Breakpoint(_) = outcome {
+                // The event was paced already, process it right away.
+                outcome = self.step_with(resume());
+            }
+            if let ShouldContinue::Break = outcome {
+                break;
+            }
+        }
+    }
+
+    pub fn state(&self) -> Rc<Cell<State>> {
+        Rc::clone(&self.state)
+    }
+}
+
+impl<O> Simulation<(), O>
+where
+    O: 'static,
+{
+    #[inline]
+    pub fn step(&mut self) -> ShouldContinue {
+        self.step_with(())
+    }
+
+    /// Advance the simulation until no events are left or a [breakpoint](Simulation::set_breakpoint)
+    /// is reached.
+    ///
+    /// Returns [`ShouldContinue::Break`] once no events are left, calling it again after a
+    /// [`ShouldContinue::Breakpoint`] continues the run.
+    pub fn run_until_empty(&mut self) -> ShouldContinue {
+        loop {
+            match self.step() {
+                ShouldContinue::Advance => {}
+                outcome => return outcome,
+            }
+        }
+    }
+
+    /// Run the simulation checking that it processes the events of `expected` in the same order.
+    ///
+    /// Stops once every expected event was processed, so a trace recorded with
+    /// [`Simulation::run_with_limit`] can be checked against a model that never ends.
+    /// [Breakpoints](Simulation::set_breakpoint) are stepped through. Only processed
+    /// events are compared, like the ones a [`ReplayRecorder`] records, so skipped
+    /// stale events and idle hook calls don't count.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first processed event that differs from the trace.
+    pub fn run_with_replay_check(
+        &mut self,
+        expected: &[(Duration, Key)],
+    ) -> Result<(), ReplayMismatch> {
+        for (index, &expected) in expected.iter().enumerate() {
+            let actual = self.step_processed();
+            if actual != Some(expected) {
+                return Err(ReplayMismatch {
+                    index,
+                    expected,
+                    actual,
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Step until an event is processed and return its time and key, `None` if the
+    /// simulation ran out of events.
+    fn step_processed(&mut self) -> Option<(Duration, Key)> {
+        loop {
+            self.last_step = None;
+            if let ShouldContinue::Break = self.step() {
+                return None;
+            }
+            if let Some(step) = self.last_step.take() {
+                return Some((step.time, step.key));
+            }
+        }
+    }
+
+    /// Process every event scheduled at or before `deadline`.
+    ///
+    /// Unlike [`Simulation::run_with_limit`] the first event past `deadline` isn't
+    /// processed, so the clock never goes beyond it. The run also stops at a
+    /// [breakpoint](Simulation::set_breakpoint), see [`RunOutcome::breakpoint`].
+    ///
+    /// If no events are left before `deadline` the [idle hook](Simulation::set_on_idle)
+    /// is called and the run continues with the work it adds.
+    pub fn run_until(&mut self, deadline: Duration) -> RunOutcome {
+        let before = self.stats.events_processed;
+        let mut breakpoint = None;
+        loop {
+            let outcome = match self.scheduler.peek() {
+                Some((time, _)) if time > deadline => break,
+                // With an empty queue the step only calls the idle hook.
+                _ => self.step(),
+            };
+            match outcome {
+                ShouldContinue::Advance => {}
+                ShouldContinue::Break => break,
+                ShouldContinue::Breakpoint(key) => {
+                    breakpoint = Some(key);
+                    break;
+                }
+            }
+        }
+        RunOutcome {
+            processed: (self.stats.events_processed - before) as usize,
+            remaining: self.scheduler.len(),
+            stopped_at: self.time(),
+            breakpoint,
+        }
+    }
+
+    /// Process every event at or before `target`, including the ones scheduled while
+    /// advancing, and then move the clock to `target`.
+    ///
+    /// Unlike [`Simulation::run_until`] the clock ends at `target` even if the last
+    /// event was earlier, so the next call continues from there.
+    /// [Breakpoints](Simulation::set_breakpoint) are stepped through. As with
+    /// [`Simulation::run_until`] the [idle hook](Simulation::set_on_idle) is called if no
+    /// events are left before `target`.
+    pub fn advance_to(&mut self, target: Duration) {
+        while self.peek_time().is_none_or(|time| time <= target) {
+            if let ShouldContinue::Break = self.step() {
+                break;
+            }
+        }
+        self.scheduler.advance_clock(target);
+    }
+
+    /// Advance the simulation until an event at or past `limit` was processed.
+    ///
+    /// Returns [`ShouldContinue::Advance`] once the limit is reached,
+    /// [`ShouldContinue::Break`] if no events are left before it and
+    /// [`ShouldContinue::Breakpoint`] at a [breakpoint](Simulation::set_breakpoint),
+    /// calling it again continues the run.
+    pub fn run_with_limit(&mut self, limit: Duration) -> ShouldContinue {
+        loop {
+            match self.step() {
+                ShouldContinue::Advance => {
+                    if self.time() >= limit {
+                        return ShouldContinue::Advance;
+                    }
+                }
+                outcome => return outcome,
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::MockClock;
+
+    fn finite(number_of_holds: u32, hold: Duration) -> GenBoxed<()> {
+        Box::new(
+            #[coroutine]
+            move |_| {
+                for _ in 0..number_of_holds {
+                    yield Action::Hold(hold);
+                }
+            },
+        )
+    }
+
+    #[test]
+    fn with_capacity_preallocates() {
+        let simulation = Simulation::<()>::with_capacity(128);
+        assert!(simulation.entities.inner.capacity() >= 128);
+        assert!(simulation.scheduler.events.capacity() >= 128);
+    }
+
+    #[test]
+    fn debug_shows_clock_and_counts() {
+        let mut simulation = Simulation::default();
+        let first = simulation.add_generator(finite(2, Duration::from_secs(3)));
+        let second = simulation.add_generator(relay(None));
+        simulation.schedule_now(first);
+        simulation.schedule_now(second);
+        simulation.step();
+        simulation.step();
+
+        assert_eq!(
+            StateCounts {
+                active: 1,
+                passive: 1,
+                completed: 0
+            },
+            simulation.state_counts()
+        );
+        assert_eq!(
+            "Simulation { time: 0ns, pending_events: 1, entities: 2, \
+             states: StateCounts { active: 1, passive: 1, completed: 0 }, .. }",
+            format!("{:?}", simulation)
+        );
+    }
+
+    #[test]
+    fn live_keys_skip_removed_entities() {
+        let mut simulation = Simulation::default();
+        let keys: Vec<Key> = (0..4)
+            .map(|_| simulation.add_generator(relay(None)))
+            .collect();
+        simulation.entities.remove(keys[1]);
+
+        let live: Vec<Key> = simulation.live_keys().collect();
+        assert_eq!(vec![keys[0], keys[2], keys[3]], live);
+    }
+
+    #[test]
+    fn component_steps_count_resumes() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(3, Duration::from_secs(1)));
+        simulation.schedule_now(key);
+        assert_eq!(Some(0), simulation.component_steps(key));
+        simulation.step();
+        simulation.step();
+        assert_eq!(Some(2), simulation.component_steps(key));
+    }
+
+    #[test]
+    fn events_are_counted_by_category() {
+        const ARRIVAL: u16 = 1;
+        const DEPARTURE: u16 = 2;
+        let mut simulation = Simulation::default();
+        for (category, count) in [(ARRIVAL, 3), (DEPARTURE, 2)] {
+            for _ in 0..count {
+                let key = simulation.add_generator(finite(1, Duration::from_secs(1)));
+                simulation.schedule_tagged(Duration::from_secs(1), key, category);
+            }
+        }
+        simulation.run_until_empty();
+
+        let stats = simulation.stats();
+        assert_eq!(3, stats.events_in_category(ARRIVAL));
+        assert_eq!(2, stats.events_in_category(DEPARTURE));
+        // The follow up events after each hold are untagged.
+        assert_eq!(5, stats.events_in_category(0));
+        assert_eq!(10, stats.events_processed);
+    }
+
+    #[test]
+    fn replay_records_every_event() {
+        let mut simulation = Simulation::default();
+        simulation.record_replay();
+        for holds in 1..=3 {
+            let key = simulation.add_generator(finite(holds, Duration::from_secs(holds as u64)));
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+
+        let replay = simulation.replay_recorder().unwrap();
+        assert_eq!(simulation.stats().events_processed as usize, replay.len());
+        assert_eq!((Duration::ZERO, Key::new(0)), replay.events()[0]);
+        assert!(replay
+            .events()
+            .windows(2)
+            .all(|pair| pair[0].0 <= pair[1].0));
+
+        #[cfg(feature = "serde")]
+        {
+            let mut saved = Vec::new();
+            replay.save(&mut saved).unwrap();
+            assert_eq!(*replay, ReplayRecorder::load(saved.as_slice()).unwrap());
+        }
+    }
+
+    #[test]
+    fn replay_check_detects_divergence() {
+        let build = |first_hold: u64| {
+            let mut simulation = Simulation::default();
+            let first = simulation.add_generator(finite(2, Duration::from_secs(first_hold)));
+            let second = simulation.add_generator(finite(2, Duration::from_secs(3)));
+            simulation.schedule_now(first);
+            simulation.schedule_now(second);
+            simulation
+        };
+
+        let mut original = build(2);
+        original.record_replay();
+        original.run_until_empty();
+        let trace = original.replay_recorder().unwrap().events().to_vec();
+
+        assert_eq!(Ok(()), build(2).run_with_replay_check(&trace));
+
+        // The first entity now holds for 4 seconds instead of 2.
+        let mismatch = build(4).run_with_replay_check(&trace).unwrap_err();
+        assert_eq!(
+            ReplayMismatch {
+                index: 2,
+                expected: (Duration::from_secs(2), Key::new(0)),
+                actual: Some((Duration::from_secs(3), Key::new(1))),
+            },
+            mismatch
+        );
+        assert_eq!(
+            "event 2 should be #0 at 2s but was #1 at 3s",
+            mismatch.to_string()
+        );
+    }
+
+    #[test]
+    fn time_in_seconds() {
+        let mut simulation = Simulation::default();
+        let clock = simulation.clock();
+        let key = simulation.add_generator(finite(1, Duration::from_millis(2500)));
+        simulation.schedule_now(key);
+        assert_eq!(0.0, simulation.now_secs());
+        simulation.run_until_empty();
+        assert_eq!(2.5, simulation.now_secs());
+        assert_eq!(2.5, clock.secs());
+    }
+
+    #[test]
+    fn schedule_if_alive_skips_dead_entities() {
+        let mut simulation = Simulation::default();
+        let alive = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        let removed = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        let passive = simulation.add_generator(relay(None));
+        simulation.entities.remove(removed);
+        *simulation.entities.get_state_mut(passive).unwrap() = EntityState::Passive;
+
+        assert!(!simulation.schedule_if_alive(Duration::ZERO, removed));
+        assert!(!simulation.schedule_if_alive(Duration::ZERO, passive));
+        assert!(!simulation.schedule_if_alive(Duration::ZERO, Key::new(42)));
+        assert!(simulation.scheduler.events.is_empty());
+
+        assert!(simulation.schedule_if_alive(Duration::from_secs(2), alive));
+        assert_eq!(
+            vec![(Duration::from_secs(2), alive)],
+            simulation.dump_queue()
+        );
+    }
+
+    #[test]
+    fn run_until_predicate_stops_early() {
+        let mut simulation = Simulation::default();
+        let keys: Vec<Key> = (1..=3)
+            .map(|holds| simulation.add_generator(finite(holds, Duration::from_secs(1))))
+            .collect();
+        for &key in &keys {
+            simulation.schedule_now(key);
+        }
+
+        let completed = |simulation: &Simulation<()>| {
+            keys.iter()
+                .filter(|&&key| simulation.entity_state(key) == Some(EntityState::Completed))
+                .count()
+        };
+        simulation.run_until_predicate(|| (), |simulation| completed(simulation) == 1);
+
+        assert_eq!(1, completed(&simulation));
+        assert_eq!(Duration::from_secs(1), simulation.time());
+        assert!(!simulation.scheduler.events.is_empty());
+    }
+
+    // Activates `next` (if any) and then waits to be activated.
+    fn relay(next: Option<Key>) -> GenBoxed<()> {
+        Box::new(
+            #[coroutine]
+            move |_| {
+                if let Some(next) = next {
+                    yield Action::ActivateOne(next);
+                }
+                yield Action::Passivate;
+            },
+        )
+    }
+
+    #[test]
+    fn activating_entities_resume_at_the_same_time() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let waiter = |name: &'static str, log: Rc<RefCell<Vec<_>>>| -> GenBoxed<()> {
+            Box::new(
+                #[coroutine]
+                move |_| {
+                    yield Action::Passivate;
+                    log.borrow_mut().push(name);
+                },
+            )
+        };
+
+        let mut simulation = Simulation::default();
+        let a = simulation.add_generator(waiter("a", Rc::clone(&log)));
+        let b = simulation.add_generator(waiter("b", Rc::clone(&log)));
+        let c = simulation.add_generator(waiter("c", Rc::clone(&log)));
+        let caller_log = Rc::clone(&log);
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Hold(Duration::from_secs(1));
+                yield Action::ActivateOne(a);
+                caller_log.borrow_mut().push("caller after one");
+                yield Action::ActivateMany(vec![b, c]);
+                caller_log.borrow_mut().push("caller after many");
+            },
+        ));
+        for key in [a, b, c, caller] {
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+
+        // Every entity ran to completion without time advancing past the hold.
+        assert_eq!(Duration::from_secs(1), simulation.time());
+        let log = log.borrow();
+        assert_eq!(5, log.len());
+        for name in ["a", "b", "c", "caller after one", "caller after many"] {
+            assert!(log.contains(&name), "{} didn't run", name);
+        }
+        for key in [a, b, c, caller] {
+            assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+        }
+    }
+
+    #[test]
+    fn activate_many_buffers_are_reused() {
+        let mut simulation = Simulation::default();
+        let pool = simulation.key_buffers();
+        let waiters: Vec<Key> = (0..3)
+            .map(|_| simulation.add_generator(relay(None)))
+            .collect();
+        for &key in &waiters {
+            *simulation.entities.get_state_mut(key).unwrap() = EntityState::Passive;
+        }
+        let caller_pool = pool.clone();
+        let targets = waiters.clone();
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let mut buffer = caller_pool.take();
+                buffer.extend_from_slice(&targets);
+                yield Action::ActivateMany(buffer);
+            },
+        ));
+        simulation.schedule_now(caller);
+
+        assert!(pool.is_empty());
+        simulation.step();
+        assert_eq!(1, pool.len());
+
+        let buffer = pool.take();
+        assert!(buffer.is_empty());
+        assert!(buffer.capacity() >= 3);
+        for key in waiters {
+            assert_eq!(Some(EntityState::Active), simulation.entity_state(key));
+        }
+    }
+
+    #[test]
+    fn export_dot_contains_activation_edges() {
+        let mut simulation = Simulation::default();
+        simulation.record_activations();
+        // Chain: first -> second -> third, the first one starts and the others wait.
+        let third = simulation.add_generator(relay(None));
+        let second = simulation.add_generator(relay(Some(third)));
+        let first = simulation.add_generator(relay(Some(second)));
+        *simulation.entities.get_state_mut(second).unwrap() = EntityState::Passive;
+        *simulation.entities.get_state_mut(third).unwrap() = EntityState::Passive;
+        simulation.schedule_now(first);
+        simulation.run_until_empty();
+
+        let graph = simulation.activation_graph().unwrap();
+        assert_eq!(1, graph.count(first, second));
+        assert_eq!(1, graph.count(second, third));
+        assert_eq!(0, graph.count(third, first));
+
+        let dot = simulation.export_dot();
+        assert!(dot.starts_with("digraph activations {"));
+        assert!(dot.contains("    2 -> 1;\n"));
+        assert!(dot.contains("    1 -> 0;\n"));
+        assert!(dot.contains("    0 [label=\"0\"];\n"));
+    }
+
+    #[test]
+    fn export_dot_skips_reaped_slots() {
+        let mut simulation = Simulation::default();
+        simulation.record_activations();
+        let lone = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        let second = simulation.add_generator(relay(None));
+        let first = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ActivateOne(second);
+            },
+        ));
+        *simulation.entities.get_state_mut(second).unwrap() = EntityState::Passive;
+        simulation.schedule_now(lone);
+        simulation.schedule_now(first);
+        simulation.run_until_empty();
+        assert_eq!(2, simulation.reap_completed());
+
+        let dot = simulation.export_dot();
+        assert!(!dot.contains(&format!("    {} [", lone.id)));
+        // A reaped entity with recorded activations is kept for its edges.
+        assert!(dot.contains(&format!("    {0} [label=\"{0}\"];\n", first.id)));
+        assert!(dot.contains(&format!("    {0} [label=\"{0}\"];\n", second.id)));
+        assert!(dot.contains(&format!("    {} -> {};\n", first.id, second.id)));
+    }
+
+    #[test]
+    fn activate_many_is_not_applied_if_a_target_is_invalid() {
+        let mut simulation = Simulation::default();
+        let a = simulation.add_generator(relay(None));
+        let b = simulation.add_generator(relay(None));
+        let c = simulation.add_generator(relay(None));
+        simulation.schedule_now(a);
+        simulation.schedule_now(c);
+        simulation.step();
+        simulation.step();
+        // `b` was never resumed so it is still active.
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ActivateMany(vec![a, b, c]);
+            },
+        ));
+        simulation.schedule_now(caller);
+        simulation.schedule(Duration::from_secs(1), b);
+        let pending = simulation.dump_queue();
+
+        let result = simulation.try_step_with(());
+
+        assert!(matches!(
+            result,
+            Err(StepError::AlreadyActive { activator, target }) if activator == caller && target == b
+        ));
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(a));
+        assert_eq!(Some(EntityState::Active), simulation.entity_state(b));
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(c));
+        assert_eq!(pending[1..], simulation.dump_queue()[..]);
+    }
+
+    #[test]
+    fn finished_entities_report_completed_until_reaped() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        let never_added = Key::new(1);
+        simulation.schedule_now(key);
+        simulation.run_until_empty();
+
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+        assert_eq!(None, simulation.entity_state(never_added));
+        assert_eq!(Some(2), simulation.component_steps(key));
+        assert_eq!(0, simulation.live_keys().count());
+
+        assert_eq!(1, simulation.reap_completed());
+        assert_eq!(None, simulation.entity_state(key));
+    }
+
+    #[test]
+    fn reaped_slots_are_reused() {
+        let mut simulation = Simulation::default();
+        let keys: Vec<Key> = (0..4)
+            .map(|hold| simulation.add_generator(finite(1, Duration::from_secs(hold))))
+            .collect();
+        for &key in &keys[..3] {
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+        simulation.watch(
+            keys[0],
+            Box::new(|_, _, _| panic!("watcher of a reaped entity")),
+        );
+
+        assert_eq!(3, simulation.reap_completed());
+        assert_eq!(0, simulation.reap_completed());
+        for &key in &keys[..3] {
+            assert_eq!(None, simulation.entity_state(key));
+        }
+
+        let mut reused: Vec<Key> = (0..4)
+            .map(|_| simulation.add_generator(finite(0, Duration::ZERO)))
+            .collect();
+        let fresh = reused.pop().unwrap();
+        reused.sort();
+        assert_eq!(keys[..3], reused[..]);
+        assert_eq!(Key::new(4), fresh);
+        for key in reused {
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+        assert_eq!(Some(EntityState::Active), simulation.entity_state(keys[3]));
+    }
+
+    #[test]
+    fn completed_keys_lists_finished_entities() {
+        let mut simulation = Simulation::default();
+        let short = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        let waiting = simulation.add_generator(relay(None));
+        let long = simulation.add_generator(finite(3, Duration::from_secs(1)));
+        for key in [short, waiting, long] {
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+
+        assert_eq!(vec![short, long], simulation.completed_keys());
+        assert_eq!(vec![waiting], simulation.live_keys().collect::<Vec<_>>());
+        assert_eq!(2, simulation.state_counts().completed);
+
+        simulation.reap_completed();
+        assert!(simulation.completed_keys().is_empty());
+    }
+
+    #[test]
+    fn detached_entities_can_be_reattached() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(2, Duration::from_secs(1)));
+        simulation.schedule_now(key);
+        simulation.step();
+        assert_eq!(vec![(Duration::from_secs(1), key)], simulation.dump_queue());
+
+        let (gen, state) = simulation.detach(key).unwrap();
+        assert!(simulation.dump_queue().is_empty());
+        assert_eq!(None, simulation.entity_state(key));
+        assert!(simulation.detach(key).is_none());
+
+        let key = simulation.reattach(gen, state);
+        simulation.schedule_now(key);
+        simulation.run_until_empty();
+
+        // Only the second hold was left.
+        assert_eq!(Duration::from_secs(1), simulation.time());
+        assert_eq!(Some(2), simulation.component_steps(key));
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+    }
+
+    #[test]
+    fn passive_entities_can_be_transferred() {
+        let mut source = Simulation::default();
+        let mut destination = Simulation::default();
+        let filler = destination.add_generator(relay(None));
+        let key = source.add_generator(relay(None));
+        source.schedule_now(key);
+        source.step();
+        assert_eq!(Some(EntityState::Passive), source.entity_state(key));
+
+        let moved = source.transfer_to(key, &mut destination).unwrap();
+        assert_eq!(None, source.entity_state(key));
+        assert_ne!(filler, moved);
+        assert_eq!(Some(EntityState::Passive), destination.entity_state(moved));
+
+        let activator = destination.add_generator(relay(Some(moved)));
+        destination.schedule_now(activator);
+        destination.run_until_empty();
+        assert_eq!(
+            Some(EntityState::Completed),
+            destination.entity_state(moved)
+        );
+        assert!(source.transfer_to(key, &mut destination).is_none());
+    }
+
+    #[test]
+    fn reset_clears_the_queue_and_the_clock() {
+        let mut simulation = Simulation::default();
+        let holding = simulation.add_generator(finite(3, Duration::from_secs(1)));
+        let waiting = simulation.add_generator(relay(None));
+        let done = simulation.add_generator(finite(0, Duration::ZERO));
+        for key in [holding, waiting, done] {
+            simulation.schedule_now(key);
+        }
+        let clock = simulation.clock();
+        simulation.run_with_limit(Duration::from_secs(2));
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(waiting));
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let watcher_changes = Rc::clone(&changes);
+        simulation.watch(
+            waiting,
+            Box::new(move |_, old, new| watcher_changes.borrow_mut().push((old, new))),
+        );
+
+        simulation.reset();
+
+        assert_eq!(Duration::ZERO, simulation.time());
+        assert_eq!(Duration::ZERO, clock.time());
+        assert!(simulation.dump_queue().is_empty());
+        assert_eq!(Some(EntityState::Active), simulation.entity_state(waiting));
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(done));
+        assert_eq!(
+            vec![(EntityState::Passive, EntityState::Active)],
+            *changes.borrow()
+        );
+
+        // The holding entity already yielded its last hold so it completes right away.
+        simulation.schedule_now(holding);
+        simulation.run_until_empty();
+        assert_eq!(Duration::ZERO, simulation.time());
+        assert_eq!(
+            Some(EntityState::Completed),
+            simulation.entity_state(holding)
+        );
+    }
+
+    #[test]
+    fn reset_forgets_a_pending_breakpoint() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_and_schedule(finite(1, Duration::from_secs(1)), Duration::ZERO);
+        simulation.set_breakpoint(key);
+        assert_eq!(ShouldContinue::Breakpoint(key), simulation.step());
+
+        simulation.reset();
+        simulation.schedule_now(key);
+        assert_eq!(ShouldContinue::Breakpoint(key), simulation.step());
+        assert_eq!(ShouldContinue::Advance, simulation.step());
+    }
+
+    #[test]
+    fn run_until_reports_leftover_events() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(5, Duration::from_secs(1)));
+        simulation.schedule_now(key);
+
+        let outcome = simulation.run_until(Duration::from_millis(2500));
+
+        // Resumed at 0, 1 and 2 seconds, the event at 3 seconds is left.
+        assert_eq!(
+            RunOutcome {
+                processed: 3,
+                remaining: 1,
+                stopped_at: Duration::from_secs(2),
+                breakpoint: None,
+            },
+            outcome
+        );
+        assert!(!outcome.drained());
+
+        let outcome = simulation.run_until(Duration::from_secs(10));
+        assert_eq!(3, outcome.processed);
+        assert!(outcome.drained());
+        assert_eq!(Duration::from_secs(5), outcome.stopped_at);
+    }
+
+    #[test]
+    fn idle_hook_can_inject_work() {
+        let mut simulation = Simulation::default();
+        let first = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        simulation.schedule_now(first);
+        let calls = Rc::new(Cell::new(0));
+        let hook_calls = Rc::clone(&calls);
+        simulation.set_on_idle(Box::new(move |simulation| {
+            hook_calls.set(hook_calls.get() + 1);
+            if hook_calls.get() == 1 {
+                let key = simulation.add_generator(finite(1, Duration::from_secs(2)));
+                simulation.schedule_now(key);
+            }
+        }));
+
+        simulation.run_until_empty();
+
+        // Once when the first entity finished and once after the injected one.
+        assert_eq!(2, calls.get());
+        assert_eq!(Duration::from_secs(3), simulation.time());
+        assert_eq!(2, simulation.completed_keys().len());
+    }
+
+    #[test]
+    fn run_until_calls_the_idle_hook() {
+        let mut simulation = Simulation::default();
+        let secs = Duration::from_secs;
+        simulation.add_and_schedule(finite(1, secs(1)), Duration::ZERO);
+        let calls = Rc::new(Cell::new(0));
+        let hook_calls = Rc::clone(&calls);
+        simulation.set_on_idle(Box::new(move |simulation| {
+            hook_calls.set(hook_calls.get() + 1);
+            if hook_calls.get() == 1 {
+                simulation.add_and_schedule(finite(1, secs(2)), Duration::ZERO);
+            }
+        }));
+
+        let outcome = simulation.run_until(secs(10));
+
+        // Once when the first entity finished and once after the injected one.
+        assert_eq!(2, calls.get());
+        assert_eq!(4, outcome.processed);
+        assert!(outcome.drained());
+        assert_eq!(secs(3), simulation.time());
+
+        simulation.advance_to(secs(20));
+        assert_eq!(3, calls.get());
+        assert_eq!(secs(20), simulation.time());
+    }
+
+    #[test]
+    fn run_real_time_keeps_up_with_the_wall_clock() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(2, Duration::from_millis(20)));
+        simulation.schedule_now(key);
+
+        let start = Instant::now();
+        simulation.run_real_time(|| ());
+
+        assert_eq!(Duration::from_millis(40), simulation.time());
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+
+    #[test]
+    fn run_real_time_with_a_mock_clock() {
+        let mut simulation = Simulation::default();
+        let millis = Duration::from_millis;
+        let key = simulation.add_generator(finite(2, millis(20)));
+        simulation.schedule(millis(10), key);
+        simulation.set_speed_factor(2.0);
+
+        let mut clock = MockClock::new();
+        simulation.run_real_time_with(&mut clock, || ());
+
+        assert_eq!(millis(50), simulation.time());
+        assert_eq!(&[millis(5), millis(10), millis(10)], clock.sleeps());
+        assert_eq!(millis(25), clock.now());
+    }
+
+    #[test]
+    #[should_panic(expected = "speed factor must be finite and greater than zero")]
+    fn speed_factor_must_be_positive() {
+        Simulation::<()>::default().set_speed_factor(0.0);
+    }
+
+    #[test]
+    fn drain_completed_returns_completion_times() {
+        let mut simulation = Simulation::default();
+        let keys: Vec<Key> = [3, 1, 2]
+            .into_iter()
+            .map(|holds| simulation.add_generator(finite(holds, Duration::from_secs(1))))
+            .collect();
+        let waiting = simulation.add_generator(relay(None));
+        for &key in keys.iter().chain([&waiting]) {
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+
+        let secs = Duration::from_secs;
+        assert_eq!(
+            vec![(keys[0], secs(3)), (keys[1], secs(1)), (keys[2], secs(2))],
+            simulation.drain_completed()
+        );
+        for key in keys {
+            assert_eq!(None, simulation.entity_state(key));
+        }
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(waiting));
+        assert!(simulation.drain_completed().is_empty());
+    }
+
+    #[test]
+    fn completed_generators_return_a_value() {
+        let mut simulation: Simulation<(), u32> = Simulation::default();
+        let key = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                let mut total = 0;
+                for i in 1..=4 {
+                    total += i;
+                    yield Action::Hold(Duration::from_secs(1));
+                }
+                total
+            },
+        ));
+        simulation.schedule_now(key);
+
+        simulation.step();
+        assert_eq!(None, simulation.take_result(key));
+        simulation.run_until_empty();
+
+        assert_eq!(Some(10), simulation.take_result(key));
+        assert_eq!(None, simulation.take_result(key));
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+    }
+
+    #[test]
+    fn activate_set_activates_each_key_once() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let waiters: Vec<Key> = (0..3)
+            .map(|id| {
+                let log = Rc::clone(&log);
+                simulation.add_generator(Box::new(
+                    #[coroutine]
+                    move |_| {
+                        yield Action::Passivate;
+                        log.borrow_mut().push(id);
+                    },
+                ))
+            })
+            .collect();
+        let finished = simulation.add_generator(finite(0, Duration::ZERO));
+        for &key in waiters.iter().chain([&finished]) {
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+
+        let mut set = KeySet::new();
+        for key in [
+            waiters[2], waiters[0], waiters[2], finished, waiters[1], waiters[0],
+        ] {
+            set.insert(key);
+        }
+        assert_eq!(4, set.len());
+        let targets = simulation.activate_set(&set);
+        assert_eq!(waiters, targets);
+
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ActivateMany(targets);
+            },
+        ));
+        simulation.schedule_now(caller);
+        simulation.run_until_empty();
+
+        assert_eq!(vec![0, 1, 2], *log.borrow());
+    }
+
+    #[test]
+    fn activate_many_ignores_duplicate_keys() {
+        let resumes = Rc::new(Cell::new(0));
+        let mut simulation = Simulation::default();
+        let waiter_resumes = Rc::clone(&resumes);
+        let waiter = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| loop {
+                yield Action::Passivate;
+                waiter_resumes.set(waiter_resumes.get() + 1);
+            },
+        ));
+        simulation.schedule_now(waiter);
+        simulation.step();
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ActivateMany(vec![waiter, waiter]);
+            },
+        ));
+        simulation.schedule_now(caller);
+        simulation.record_activations();
+
+        simulation.run_until_empty();
+
+        assert_eq!(1, resumes.get());
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(waiter));
+        assert_eq!(
+            1,
+            simulation.activation_graph().unwrap().count(caller, waiter)
+        );
+    }
+
+    #[test]
+    fn activating_a_removed_entity_is_an_error() {
+        let mut simulation = Simulation::default();
+        let removed = simulation.add_generator(relay(None));
+        let caller = simulation.add_generator(relay(Some(removed)));
+        simulation.detach(removed);
+        simulation.schedule_now(caller);
+
+        let result = simulation.try_step_with(());
+
+        assert!(matches!(result, Err(StepError::MissingComponent(key)) if key == removed));
+        assert!(simulation.dump_queue().is_empty());
+        assert_eq!(Some(EntityState::Active), simulation.entity_state(caller));
+    }
+
+    #[test]
+    fn schedule_at_uses_absolute_times() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(1, Duration::from_secs(2)));
+        simulation.schedule_now(key);
+        simulation.step();
+        let other = simulation.add_generator(finite(0, Duration::ZERO));
+
+        simulation
+            .schedule_at(Duration::from_secs(5), other)
+            .unwrap();
+
+        let secs = Duration::from_secs;
+        assert_eq!(
+            vec![(secs(2), key), (secs(5), other)],
+            simulation.dump_queue()
+        );
+    }
+
+    #[test]
+    fn reschedule_moves_the_pending_event() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let timeout_log = Rc::clone(&log);
+        let timeout = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                timeout_log.borrow_mut().push("timeout");
+            },
+        ));
+        let other = simulation.add_generator(finite(0, Duration::ZERO));
+        let secs = Duration::from_secs;
+        simulation.schedule(secs(10), timeout);
+        simulation.schedule(secs(5), other);
+
+        assert!(simulation.reschedule(timeout, secs(2)));
+        assert!(!simulation.reschedule(Key::new(42), secs(1)));
+        assert_eq!(
+            vec![(secs(2), timeout), (secs(5), other)],
+            simulation.dump_queue()
+        );
+
+        simulation.step();
+        assert_eq!(secs(2), simulation.time());
+        assert_eq!(vec!["timeout"], *log.borrow());
+        assert_eq!(vec![(secs(5), other)], simulation.dump_queue());
+    }
+
+    #[test]
+    fn past_events_can_be_rejected() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(1, Duration::from_secs(2)));
+        simulation.schedule_now(key);
+        simulation.run_until_empty();
+        simulation.set_reject_past_events(true);
+
+        let other = simulation.add_generator(finite(0, Duration::ZERO));
+        let result = simulation.schedule_at(Duration::from_secs(1), other);
+
+        assert_eq!(
+            Err(PastEventError {
+                key: other,
+                time: Duration::from_secs(1),
+                now: Duration::from_secs(2)
+            }),
+            result
+        );
+        assert!(simulation.dump_queue().is_empty());
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "was scheduled at 1s but the simulation is at 2s")]
+    fn past_events_panic_in_debug_builds() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(finite(1, Duration::from_secs(2)));
+        simulation.schedule_now(key);
+        simulation.run_until_empty();
+
+        let _ = simulation.schedule_at(Duration::from_secs(1), key);
+    }
+
+    #[test]
+    fn groups_are_passivated_and_activated_together() {
+        let mut simulation = Simulation::default();
+        let machines: Vec<Key> = (0..3)
+            .map(|_| simulation.add_generator(finite(2, Duration::from_secs(1))))
+            .collect();
+        let finished = simulation.add_generator(finite(0, Duration::ZERO));
+        for &key in machines.iter().chain([&finished]) {
+            simulation.schedule_now(key);
+        }
+        for _ in 0..4 {
+            simulation.step();
+        }
+        let group = simulation.create_group(machines.iter().copied().chain([finished]));
+        assert_eq!(4, simulation.group(group).members().len());
+
+        simulation.passivate_group(group);
+        for &key in &machines {
+            assert_eq!(Some(EntityState::Passive), simulation.entity_state(key));
+        }
+        assert_eq!(
+            Some(EntityState::Completed),
+            simulation.entity_state(finished)
+        );
+        assert!(simulation.is_idle());
+
+        simulation.activate_group(group);
+        let scheduled: Vec<Key> = simulation
+            .dump_queue()
+            .into_iter()
+            .map(|(_, key)| key)
+            .collect();
+        assert_eq!(machines, scheduled);
+        simulation.run_until_empty();
+        for &key in &machines {
+            assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+        }
+    }
+
+    #[test]
+    fn multi_applies_every_action() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let waiter_log = Rc::clone(&log);
+        let waiter = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Passivate;
+                waiter_log.borrow_mut().push("waiter");
+            },
+        ));
+        simulation.schedule_now(waiter);
+        simulation.step();
+        let caller_log = Rc::clone(&log);
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Multi(vec![
+                    Action::Hold(Duration::from_secs(5)),
+                    Action::ActivateOne(waiter),
+                ]);
+                caller_log.borrow_mut().push("caller");
+            },
+        ));
+        simulation.schedule_now(caller);
+        simulation.step();
+
+        let secs = Duration::from_secs;
+        assert_eq!(
+            vec![(secs(0), waiter), (secs(5), caller)],
+            simulation.dump_queue()
+        );
+        simulation.run_until_empty();
+        assert_eq!(vec!["waiter", "caller"], *log.borrow());
+        assert_eq!(secs(5), simulation.time());
+    }
+
+    #[test]
+    fn activate_many_resumes_targets_in_list_order() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let waiters: Vec<Key> = ["a", "b", "c"]
+            .into_iter()
+            .map(|name| {
+                let log = Rc::clone(&log);
+                simulation.add_generator(Box::new(
+                    #[coroutine]
+                    move |_| {
+                        yield Action::Passivate;
+                        log.borrow_mut().push(name);
+                    },
+                ))
+            })
+            .collect();
+        let caller_log = Rc::clone(&log);
+        let targets = vec![waiters[2], waiters[0], waiters[1]];
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Hold(Duration::from_secs(1));
+                yield Action::ActivateMany(targets);
+                caller_log.borrow_mut().push("caller");
+            },
+        ));
+        for &key in waiters.iter().chain([&caller]) {
+            simulation.schedule_now(key);
+        }
+
+        simulation.run_until_empty();
+
+        assert_eq!(vec!["caller", "c", "a", "b"], *log.borrow());
+    }
+
+    #[test]
+    fn activate_small_matches_activate_many() {
+        let run = |targets: usize, small: bool| {
+            let log = Rc::new(RefCell::new(Vec::new()));
+            let mut simulation = Simulation::default();
+            let waiters: Vec<Key> = (0..4)
+                .map(|id| {
+                    let log = Rc::clone(&log);
+                    simulation.add_generator(Box::new(
+                        #[coroutine]
+                        move |_| {
+                            yield Action::Passivate;
+                            log.borrow_mut().push(id);
+                        },
+                    ))
+                })
+                .collect();
+            let mut listed = [None; 4];
+            for (slot, &key) in listed.iter_mut().zip(waiters.iter().rev().take(targets)) {
+                *slot = Some(key);
+            }
+            let caller = simulation.add_generator(Box::new(
+                #[coroutine]
+                move |_| {
+                    yield Action::Hold(Duration::from_secs(1));
+                    if small {
+                        yield Action::ActivateSmall(listed);
+                    } else {
+                        yield Action::ActivateMany(listed.into_iter().flatten().collect());
+                    }
+                },
+            ));
+            for &key in waiters.iter().chain([&caller]) {
+                simulation.schedule_now(key);
+            }
+            simulation.run_until_empty();
+            (log.take(), simulation.state_counts(), simulation.time())
+        };
+
+        for targets in 1..=4 {
+            let small = run(targets, true);
+            assert_eq!(run(targets, false), small);
+            assert_eq!(targets, small.0.len());
+        }
+    }
+
+    #[test]
+    fn activate_many_then_hold_wakes_targets_and_holds_the_caller() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let consumers: Vec<Key> = ["a", "b"]
+            .into_iter()
+            .map(|name| {
+                let log = Rc::clone(&log);
+                simulation.add_generator(Box::new(
+                    #[coroutine]
+                    move |_| {
+                        yield Action::Passivate;
+                        log.borrow_mut().push(name);
+                    },
+                ))
+            })
+            .collect();
+        for &key in &consumers {
+            simulation.schedule_now(key);
+            simulation.step();
+        }
+        let producer_log = Rc::clone(&log);
+        let targets = consumers.clone();
+        let producer = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ActivateManyThenHold(targets, Duration::from_secs(3));
+                producer_log.borrow_mut().push("producer");
+            },
+        ));
+        simulation.schedule_now(producer);
+        simulation.step();
+
+        let secs = Duration::from_secs;
+        let expected = vec![
+            (secs(0), consumers[0]),
+            (secs(0), consumers[1]),
+            (secs(3), producer),
+        ];
+        assert_eq!(expected, simulation.dump_queue());
+        simulation.run_until_empty();
+        assert_eq!(vec!["a", "b", "producer"], *log.borrow());
+        assert_eq!(Some(2), simulation.component_steps(producer));
+    }
+
+    #[test]
+    fn conflicting_multi_is_rejected() {
+        let mut simulation = Simulation::default();
+        let other = simulation.add_generator(finite(0, Duration::ZERO));
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Multi(vec![
+                    Action::ActivateOne(other),
+                    Action::Hold(Duration::from_secs(1)),
+                    Action::Passivate,
+                ]);
+            },
+        ));
+        simulation.schedule_now(caller);
+
+        assert_eq!(
+            Err(StepError::InvalidMulti(caller)),
+            simulation.try_step_with(())
+        );
+        assert!(simulation.is_idle());
+    }
+
+    #[test]
+    #[cfg(feature = "catch-panics")]
+    fn panicking_components_are_removed() {
+        let mut simulation = Simulation::default();
+        let worker = simulation.add_generator(finite(3, Duration::from_secs(1)));
+        let faulty = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                yield Action::Hold(Duration::from_secs(2));
+                panic!("faulty component");
+            },
+        ));
+        simulation.schedule_now(worker);
+        simulation.schedule_now(faulty);
+
+        let mut errors = Vec::new();
+        while simulation.has_pending() {
+            if let Err(error) = simulation.try_step_with(()) {
+                errors.push(error);
+            }
+        }
+
+        assert_eq!(vec![StepError::ComponentPanicked(faulty)], errors);
+        assert_eq!(None, simulation.entity_state(faulty));
+        assert_eq!(
+            Some(EntityState::Completed),
+            simulation.entity_state(worker)
+        );
+        assert_eq!(Duration::from_secs(3), simulation.time());
+    }
+
+    #[test]
+    fn simulations_can_start_at_any_time() {
+        let mut simulation = Simulation::with_start_time(Duration::from_secs(100));
+        assert_eq!(Duration::from_secs(100), simulation.time());
+        let key = simulation.add_generator(finite(0, Duration::ZERO));
+
+        simulation.schedule(Duration::from_secs(5), key);
+
+        assert_eq!(
+            vec![(Duration::from_secs(105), key)],
+            simulation.dump_queue()
+        );
+        simulation.run_until_empty();
+        assert_eq!(Duration::from_secs(105), simulation.time());
+    }
+
+    #[test]
+    fn capped_holds_resume_at_the_cap() {
+        let resumed = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let clock = simulation.clock();
+        let log = Rc::clone(&resumed);
+        let key = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                let secs = Duration::from_secs;
+                yield Action::HoldCapped(secs(20), secs(10));
+                log.borrow_mut().push(clock.time());
+                yield Action::HoldCapped(secs(3), secs(30));
+                log.borrow_mut().push(clock.time());
+            },
+        ));
+        simulation.schedule_now(key);
+
+        simulation.run_until_empty();
+
+        let secs = Duration::from_secs;
+        assert_eq!(vec![secs(10), secs(13)], *resumed.borrow());
+    }
+
+    #[test]
+    fn wake_reasons() {
+        let mut simulation = Simulation::default();
+        let worker = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                yield Action::Passivate;
+                yield Action::Hold(Duration::from_secs(10));
+                yield Action::Hold(Duration::from_secs(1));
+            },
+        ));
+        let manager = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ActivateOne(worker);
+                yield Action::Hold(Duration::from_secs(2));
+                yield Action::Cancel(worker);
+                yield Action::ActivateOne(worker);
+            },
+        ));
+        simulation.schedule_now(worker);
+        simulation.step();
+        assert_eq!(None, simulation.last_wake_reason(manager));
+        simulation.schedule_now(manager);
+        simulation.step();
+
+        // The manager continues first and holds, then the activated worker starts its hold.
+        simulation.step();
+        simulation.step();
+        assert_eq!(
+            Some(WakeReason::Activated),
+            simulation.last_wake_reason(worker)
+        );
+        // At 2s the manager's hold expires, it cancels the worker and activates it again.
+        simulation.step();
+        assert_eq!(
+            Some(WakeReason::HoldExpired),
+            simulation.last_wake_reason(manager)
+        );
+        simulation.step();
+        simulation.step();
+        simulation.step();
+        assert_eq!(Duration::from_secs(2), simulation.time());
+        assert_eq!(
+            Some(WakeReason::Interrupted),
+            simulation.last_wake_reason(worker)
+        );
+
+        simulation.run_until_empty();
+        assert_eq!(
+            Some(WakeReason::HoldExpired),
+            simulation.last_wake_reason(worker)
+        );
+    }
+
+    #[test]
+    fn stale_targets_are_missing_components() {
+        let mut simulation = Simulation::default();
+        let worker = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        let removed = simulation.add_generator(relay(None));
+        let manager = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Hold(Duration::from_secs(1));
+                yield Action::Cancel(worker);
+                yield Action::Cancel(removed);
+                yield Action::ActivateOne(removed);
+            },
+        ));
+        for key in [worker, removed, manager] {
+            simulation.schedule_now(key);
+        }
+        let step_manager = |simulation: &mut Simulation<()>| {
+            while simulation.upcoming(1)[0].1 != manager {
+                simulation.step();
+            }
+            simulation.try_step_with(())
+        };
+
+        // The worker completes at 1s right before the manager tries to cancel it.
+        step_manager(&mut simulation).unwrap();
+        let result = step_manager(&mut simulation);
+        assert_eq!(Err(StepError::MissingComponent(worker)), result);
+        assert_eq!(Duration::from_secs(1), simulation.time());
+
+        // The caller isn't rescheduled after an error, it's scheduled again by hand.
+        simulation.detach(removed);
+        for _ in 0..2 {
+            simulation.schedule_now(manager);
+            let result = step_manager(&mut simulation);
+            assert_eq!(Err(StepError::MissingComponent(removed)), result);
+        }
+        assert!(simulation.is_idle());
+    }
+
+    #[test]
+    fn same_time_signals_resume_once() {
+        let mut simulation = Simulation::default();
+        simulation.set_coalesce_same_time(true);
+        let key = simulation.add_generator(finite(3, Duration::from_secs(1)));
+        for _ in 0..3 {
+            simulation.schedule_now(key);
+        }
+
+        simulation.step();
+
+        assert_eq!(Some(1), simulation.component_steps(key));
+        assert_eq!(2, simulation.coalesced_events());
+        assert_eq!(vec![(Duration::from_secs(1), key)], simulation.dump_queue());
+    }
+
+    #[test]
+    fn schedule_other_wakes_the_target_later() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let clock = simulation.clock();
+        let target_log = Rc::clone(&log);
+        let target = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                target_log.borrow_mut().push(("target", clock.time()));
+            },
+        ));
+        let caller_log = Rc::clone(&log);
+        let clock = simulation.clock();
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ScheduleOther(target, Duration::from_secs(5));
+                caller_log.borrow_mut().push(("caller", clock.time()));
+            },
+        ));
+        simulation.schedule_now(caller);
+
+        simulation.step();
+        assert_eq!(Some(EntityState::Active), simulation.entity_state(target));
+        simulation.run_until_empty();
+
+        let expected = vec![
+            ("caller", Duration::ZERO),
+            ("target", Duration::from_secs(5)),
+        ];
+        assert_eq!(expected, *log.borrow());
+    }
+
+    #[test]
+    fn scheduled_passive_entities_become_active() {
+        let mut simulation = Simulation::default();
+        let target = simulation.add_generator(relay(None));
+        simulation.schedule_now(target);
+        simulation.step();
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::ScheduleOther(target, Duration::from_secs(2));
+            },
+        ));
+        simulation.schedule_now(caller);
+        simulation.step();
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(target));
+
+        simulation.run_until_empty();
+
+        assert_eq!(Duration::from_secs(2), simulation.time());
+        assert_eq!(
+            Some(EntityState::Completed),
+            simulation.entity_state(target)
+        );
+    }
+
+    #[test]
+    fn activating_a_scheduled_passive_entity_resumes_it_now() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let clock = simulation.clock();
+        let target_log = Rc::clone(&log);
+        let target = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Passivate;
+                target_log.borrow_mut().push(clock.time());
+                yield Action::Passivate;
+            },
+        ));
+        simulation.schedule_now(target);
+        let caller = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Hold(Duration::from_secs(1));
+                yield Action::ScheduleOther(target, Duration::from_secs(10));
+                yield Action::Hold(Duration::from_secs(1));
+                yield Action::ActivateOne(target);
+            },
+        ));
+        simulation.schedule_now(caller);
+
+        simulation.run_until_empty();
+
+        assert_eq!(vec![Duration::from_secs(2)], *log.borrow());
+        assert_eq!(Duration::from_secs(2), simulation.time());
+    }
+
+    #[test]
+    fn run_chunked_continues_where_it_stopped() {
+        let mut simulation = Simulation::default();
+        for holds in [2, 3] {
+            let key = simulation.add_generator(finite(holds, Duration::from_secs(1)));
+            simulation.schedule_now(key);
+        }
+
+        let mut chunks = Vec::new();
+        loop {
+            let outcome = simulation.run_chunked(2, || ());
+            chunks.push(outcome.processed);
+            if outcome.drained() {
+                break;
+            }
+        }
+
+        // Each entity is resumed once per hold and once more to complete.
+        assert_eq!(vec![2, 2, 2, 1], chunks);
+        assert_eq!(7, simulation.stats().events_processed);
+        assert_eq!(0, simulation.run_chunked(2, || ()).processed);
+    }
+
+    #[test]
+    fn quiesced_entities_can_be_reactivated() {
+        let mut simulation = Simulation::default();
+        let secs = Duration::from_secs;
+        let mut keys = Vec::new();
+        for holds in [2, 4] {
+            let key = simulation.add_generator(finite(holds, secs(1)));
+            simulation.schedule_now(key);
+            keys.push(key);
+        }
+        let passive = simulation.reattach(finite(1, secs(1)), EntityState::Passive);
+        simulation.advance_to(secs(1));
+
+        let active = simulation.quiesce();
+        assert_eq!(keys, active);
+        assert!(!simulation.has_pending());
+        for key in keys.iter().copied().chain([passive]) {
+            assert_eq!(Some(EntityState::Passive), simulation.entity_state(key));
+        }
+        assert_eq!(ShouldContinue::Break, simulation.step());
+
+        let group = simulation.create_group(active);
+        simulation.activate_group(group);
+        simulation.run_until_empty();
+        // The interrupted holds are skipped, the last two holds of the second entity
+        // run from the reactivation at 1s.
+        assert_eq!(secs(3), simulation.time());
+        for &key in &keys {
+            assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+        }
+        assert_eq!(Some(EntityState::Passive), simulation.entity_state(passive));
+    }
+
+    #[test]
+    fn added_and_scheduled_entities_start_at_their_times() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let secs = Duration::from_secs;
+        let mut keys = Vec::new();
+        for start in [3, 1, 2] {
+            let log = Rc::clone(&log);
+            let gen: GenBoxed<()> = Box::new(
+                #[coroutine]
+                move |_| {
+                    log.borrow_mut().push(start);
+                    yield Action::Hold(secs(10));
+                },
+            );
+            keys.push(simulation.add_and_schedule(gen, secs(start)));
+        }
+        assert_eq!(
+            vec![(secs(1), keys[1]), (secs(2), keys[2]), (secs(3), keys[0])],
+            simulation.dump_queue()
+        );
+
+        simulation.run_until(secs(3));
+        assert_eq!(vec![1, 2, 3], *log.borrow());
+    }
+
+    #[test]
+    fn next_gap_is_the_idle_time_before_the_next_event() {
+        let mut simulation = Simulation::default();
+        assert_eq!(None, simulation.next_gap());
+        let secs = Duration::from_secs;
+        let key = simulation.add_generator(finite(2, secs(7)));
+        simulation.schedule(secs(5), key);
+        assert_eq!(Some(secs(5)), simulation.next_gap());
+
+        simulation.step();
+        assert_eq!(secs(5), simulation.time());
+        assert_eq!(Some(secs(7)), simulation.next_gap());
+    }
+
+    #[test]
+    fn higher_priority_entities_win_ties() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let mut add = |name, priority| {
+            let log = Rc::clone(&log);
+            let key = simulation.add_generator_with_priority(
+                Box::new(
+                    #[coroutine]
+                    move |_| {
+                        for _ in 0..2 {
+                            log.borrow_mut().push(name);
+                            yield Action::Hold(Duration::from_secs(1));
+                        }
+                    },
+                ),
+                priority,
+            );
+            simulation.schedule_now(key);
+            key
+        };
+        add("low", 10);
+        let high = add("high", -10);
+        add("default", 0);
+        simulation.run_until_empty();
+
+        assert_eq!(-10, simulation.priority(high));
+        // The holds are stamped with the priority too.
+        assert_eq!(
+            vec!["high", "default", "low", "high", "default", "low"],
+            *log.borrow()
+        );
+    }
+
+    #[test]
+    fn events_of_completed_entities_are_skipped() {
+        let mut simulation = Simulation::default();
+        let secs = Duration::from_secs;
+        let timeouts = simulation.add_queue("timeouts");
+        let key = simulation.add_generator(finite(1, secs(1)));
+        simulation.schedule_now(key);
+        // Still pending when the entity completes at 1 second.
+        simulation.schedule_in_queue(timeouts, secs(5), key);
+
+        let outcome = simulation.run_until(secs(10));
+        assert_eq!(2, outcome.processed);
+        assert!(outcome.drained());
+
+        let stats = simulation.stats();
+        assert_eq!(1, stats.stale_events);
+        assert_eq!(2, stats.events_processed);
+        // The clock still moves to the skipped event.
+        assert_eq!(secs(5), simulation.time());
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+    }
+
+    #[test]
+    fn step_info_describes_the_processed_event() {
+        let mut simulation = Simulation::default();
+        let secs = Duration::from_secs;
+        let last = simulation.reattach(finite(1, secs(2)), EntityState::Passive);
+        let first = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Hold(secs(1));
+                yield Action::ActivateOne(last);
+            },
+        ));
+        simulation.schedule_now(first);
+
+        let info = |key, time, action_kind| StepInfo {
+            key,
+            time,
+            action_kind,
+        };
+        let mut steps = Vec::new();
+        loop {
+            match simulation.step_with_info(()) {
+                (ShouldContinue::Advance, Some(step)) => steps.push(step),
+                (ShouldContinue::Break, None) => break,
+                other => panic!("unexpected step {:?}", other),
+            }
+        }
+        assert_eq!(
+            vec![
+                info(first, secs(0), ActionKind::Hold),
+                info(first, secs(1), ActionKind::ActivateOne),
+                info(first, secs(1), ActionKind::Completed),
+                info(last, secs(1), ActionKind::Hold),
+                info(last, secs(3), ActionKind::Completed),
+            ],
+            steps
+        );
+    }
+
+    #[test]
+    fn stats_span_the_processed_events() {
+        let mut simulation = Simulation::default();
+        assert_eq!(None, simulation.stats().span());
+        let secs = Duration::from_secs;
+        let key = simulation.add_generator(finite(3, secs(2)));
+        simulation.schedule(secs(5), key);
+        simulation.run_until_empty();
+
+        let stats = simulation.stats();
+        assert_eq!(Some(secs(5)), stats.first_event_time);
+        assert_eq!(Some(secs(11)), stats.last_event_time);
+        assert_eq!(Some(secs(6)), stats.span());
+    }
+
+    #[test]
+    fn zero_holds_are_counted_apart() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                for secs in [0, 2, 0, 0, 3] {
+                    yield Action::Hold(Duration::from_secs(secs));
+                }
+            },
+        ));
+        simulation.schedule_now(key);
+        simulation.run_until_empty();
+
+        let stats = simulation.stats();
+        assert_eq!(2, stats.holds);
+        assert_eq!(3, stats.zero_holds);
+        assert_eq!(Duration::from_secs(5), stats.hold_time);
+    }
+
+    #[test]
+    fn sojourn_times_are_recorded_at_completion() {
+        let mut simulation = Simulation::default();
+        simulation.record_sojourns(SojournRecorder::new().with_histogram());
+        let secs = Duration::from_secs;
+        for holds in [1, 3] {
+            let key = simulation.add_generator(finite(holds, secs(1)));
+            simulation.schedule_now(key);
+        }
+        simulation.advance_to(secs(2));
+        // Enters at 2s and leaves at 4s.
+        let late = simulation.add_generator(finite(2, secs(1)));
+        simulation.schedule_now(late);
+        simulation.run_until_empty();
+
+        let sojourns = simulation.sojourns().unwrap();
+        let summary = sojourns.summary();
+        assert_eq!(3, summary.count());
+        assert!((summary.mean() - 2.0).abs() < 1e-12);
+        assert_eq!(3.0, sojourns.tally().max());
+        assert_eq!(3, sojourns.histogram().unwrap().count());
+    }
+
+    #[test]
+    fn debug_checks_pass_on_a_consistent_simulation() {
+        let mut simulation = Simulation::default();
+        simulation.set_debug_checks(true);
+        let secs = Duration::from_secs;
+        for holds in 1..=2 {
+            let key = simulation.add_generator(finite(holds, secs(1)));
+            simulation.schedule_now(key);
+        }
+        simulation.run_until_empty();
+        assert_eq!(2, simulation.reap_completed());
+        let reused = simulation.add_generator(finite(1, secs(1)));
+        simulation.schedule_now(reused);
+        simulation.run_until_empty();
+        assert_eq!(secs(3), simulation.time());
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Invariant violated: Entity ID = 0 has an event pending at 5s but was removed"
+    )]
+    fn debug_checks_catch_events_of_removed_entities() {
+        let mut simulation = Simulation::default();
+        simulation.set_debug_checks(true);
+        let removed = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        let other = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        simulation.detach(removed);
+        // Bypass the cleanup done by `detach`.
+        simulation
+            .scheduler
+            .schedule(Duration::from_secs(5), removed);
+        simulation.schedule_now(other);
+        simulation.step();
+    }
+
+    #[test]
+    fn recurring_callbacks_fire_until_cancelled() {
+        let fired = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let log = Rc::clone(&fired);
+        let id = Rc::new(Cell::new(None));
+        let own_id = Rc::clone(&id);
+        let recurring = simulation.schedule_recurring(
+            Duration::from_secs(2),
+            Box::new(move |simulation: &mut Simulation<()>| {
+                log.borrow_mut().push(simulation.time());
+                if log.borrow().len() == 3 {
+                    assert!(simulation.cancel_recurring(own_id.get().unwrap()));
+                }
+            }),
+        );
+        id.set(Some(recurring));
+        assert_eq!(
+            Some(EntityState::Active),
+            simulation.entity_state(recurring.key())
+        );
+
+        simulation.run_until_empty();
+
+        let secs = Duration::from_secs;
+        assert_eq!(vec![secs(2), secs(4), secs(6)], *fired.borrow());
+        assert_eq!(None, simulation.entity_state(recurring.key()));
+        assert!(!simulation.cancel_recurring(recurring));
+    }
+
+    #[test]
+    fn pending_checks_follow_the_queue() {
+        let mut simulation = Simulation::default();
+        assert!(simulation.is_idle());
+        assert!(!simulation.has_pending());
+
+        let key = simulation.add_generator(finite(1, Duration::from_secs(1)));
+        simulation.schedule_now(key);
+        assert!(simulation.has_pending());
+        assert!(!simulation.is_idle());
+
+        simulation.step();
+        assert!(simulation.has_pending());
+        simulation.step();
+        assert!(simulation.is_idle());
+        assert!(!simulation.has_pending());
+    }
+
+    #[test]
+    fn advance_to_processes_cascading_events() {
+        let log = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let follower_log = Rc::clone(&log);
+        let follower = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Passivate;
+                follower_log.borrow_mut().push("follower");
+            },
+        ));
+        simulation.schedule_now(follower);
+        simulation.step();
+        let leader_log = Rc::clone(&log);
+        let leader = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                leader_log.borrow_mut().push("leader");
+                yield Action::ActivateOne(follower);
+                yield Action::Hold(Duration::from_secs(1));
+                leader_log.borrow_mut().push("late");
+            },
+        ));
+        simulation.schedule(Duration::from_secs(5), leader);
+
+        simulation.advance_to(Duration::from_secs(5));
+
+        assert_eq!(vec!["leader", "follower"], *log.borrow());
+        assert_eq!(Some(Duration::from_secs(6)), simulation.peek_time());
+        assert_eq!(Duration::from_secs(5), simulation.time());
+
+        simulation.advance_to(Duration::from_millis(5500));
+        assert_eq!(Duration::from_millis(5500), simulation.time());
+        assert_eq!(2, log.borrow().len());
+    }
+
+    #[test]
+    fn breakpoints_stop_before_the_event() {
+        let mut simulation = Simulation::default();
+        let watched = simulation.add_generator(finite(2, Duration::from_secs(2)));
+        let other = simulation.add_generator(finite(3, Duration::from_secs(1)));
+        simulation.schedule_now(watched);
+        simulation.schedule_now(other);
+        simulation.step();
+        simulation.set_breakpoint(watched);
+
+        let mut stops = Vec::new();
+        while let ShouldContinue::Breakpoint(key) = simulation.run_until_empty() {
+            assert_eq!(watched, key);
+            let (time, next) = simulation.upcoming(1)[0];
+            assert_eq!(watched, next);
+            stops.push(time);
+        }
+
+        let secs = Duration::from_secs;
+        assert_eq!(vec![secs(2), secs(4)], stops);
+        assert_eq!(
+            Some(EntityState::Completed),
+            simulation.entity_state(watched)
+        );
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(other));
+
+        assert!(simulation.clear_breakpoint(watched));
+        assert!(!simulation.clear_breakpoint(watched));
+    }
+
+    #[test]
+    fn clearing_the_last_breakpoint_disarms_the_resume() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_and_schedule(finite(2, Duration::from_secs(1)), Duration::ZERO);
+        simulation.set_breakpoint(key);
+        assert_eq!(ShouldContinue::Breakpoint(key), simulation.step());
+
+        assert!(simulation.clear_breakpoint(key));
+        assert_eq!(ShouldContinue::Advance, simulation.step());
+        simulation.set_breakpoint(key);
+        assert_eq!(ShouldContinue::Breakpoint(key), simulation.step());
+        assert_eq!(Duration::ZERO, simulation.time());
+    }
+
+    #[test]
+    fn run_until_stops_at_breakpoints() {
+        let mut simulation = Simulation::default();
+        let secs = Duration::from_secs;
+        let watched = simulation.add_and_schedule(finite(1, secs(2)), secs(1));
+        let other = simulation.add_and_schedule(finite(3, secs(1)), Duration::ZERO);
+        simulation.set_breakpoint(watched);
+
+        let outcome = simulation.run_until(secs(5));
+        // Only `other` ran, `watched` was scheduled first at 1 second.
+        assert_eq!(
+            RunOutcome {
+                processed: 1,
+                remaining: 2,
+                stopped_at: Duration::ZERO,
+                breakpoint: Some(watched),
+            },
+            outcome
+        );
+        assert_eq!(Some(secs(1)), simulation.peek_time());
+
+        assert!(simulation.clear_breakpoint(watched));
+        let outcome = simulation.run_until(secs(5));
+        assert_eq!(None, outcome.breakpoint);
+        assert_eq!(5, outcome.processed);
+        assert!(outcome.drained());
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(other));
+    }
+
+    #[test]
+    fn run_with_limit_stops_at_breakpoints() {
+        let mut simulation = Simulation::default();
+        let secs = Duration::from_secs;
+        let watched = simulation.add_and_schedule(finite(5, secs(1)), Duration::ZERO);
+        simulation.step();
+        simulation.set_breakpoint(watched);
+
+        assert_eq!(
+            ShouldContinue::Breakpoint(watched),
+            simulation.run_with_limit(secs(3))
+        );
+        assert_eq!(Duration::ZERO, simulation.time());
+        assert!(simulation.clear_breakpoint(watched));
+        assert_eq!(ShouldContinue::Advance, simulation.run_with_limit(secs(3)));
+        assert_eq!(secs(3), simulation.time());
+        assert_eq!(ShouldContinue::Break, simulation.run_with_limit(secs(10)));
+        assert_eq!(6, simulation.stats().events_processed);
+    }
+
+    #[test]
+    fn replay_check_ignores_stale_events() {
+        let build = || {
+            let mut simulation = Simulation::default();
+            let timeouts = simulation.add_queue("timeouts");
+            let secs = Duration::from_secs;
+            let waiter = simulation.add_and_schedule(finite(1, secs(1)), Duration::ZERO);
+            simulation.add_and_schedule(finite(1, secs(7)), Duration::ZERO);
+            // Still pending when the waiter completes, skipped at 5 seconds.
+            simulation.schedule_in_queue(timeouts, secs(5), waiter);
+            simulation
+        };
+
+        let mut original = build();
+        original.record_replay();
+        original.run_until_empty();
+        assert_eq!(1, original.stats().stale_events);
+        let trace = original.replay_recorder().unwrap().events().to_vec();
+        assert_eq!(4, trace.len());
+
+        assert_eq!(Ok(()), build().run_with_replay_check(&trace));
+    }
+
+    #[test]
+    fn replay_check_steps_through_breakpoints() {
+        let mut simulation = Simulation::default();
+        simulation.record_replay();
+        let key = simulation.add_and_schedule(finite(2, Duration::from_secs(1)), Duration::ZERO);
+        simulation.run_until_empty();
+        let trace = simulation.replay_recorder().unwrap().events().to_vec();
+
+        let mut replayed = Simulation::default();
+        replayed.add_and_schedule(finite(2, Duration::from_secs(1)), Duration::ZERO);
+        replayed.set_breakpoint(key);
+        assert_eq!(Ok(()), replayed.run_with_replay_check(&trace));
+        assert_eq!(simulation.time(), replayed.time());
+    }
+
+    #[test]
+    fn watchers_see_state_changes() {
+        let changes = Rc::new(RefCell::new(Vec::new()));
+        let mut simulation = Simulation::default();
+        let watched = simulation.add_generator(relay(None));
+        let activator = simulation.add_generator(relay(Some(watched)));
+        let watcher_changes = Rc::clone(&changes);
+        simulation.watch(
+            watched,
+            Box::new(move |key, old, new| watcher_changes.borrow_mut().push((key, old, new))),
+        );
+        simulation.schedule_now(watched);
+        simulation.schedule_now(activator);
+        simulation.run_until_empty();
+
+        use EntityState::*;
+        assert_eq!(
+            vec![
+                (watched, Active, Passive),
+                (watched, Passive, Active),
+                (watched, Active, Completed)
+            ],
+            *changes.borrow()
+        );
+    }
+
+    #[test]
+    fn timeline_reconstructs_state_intervals() {
+        let mut simulation = Simulation::default();
+        simulation.record_timeline();
+        let worker = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                yield Action::Hold(Duration::from_secs(2));
+                yield Action::Passivate;
+                yield Action::Hold(Duration::from_secs(1));
+            },
+        ));
+        let waker = simulation.add_generator(Box::new(
+            #[coroutine]
+            move |_| {
+                yield Action::Hold(Duration::from_secs(5));
+                yield Action::ActivateOne(worker);
+            },
+        ));
+        simulation.schedule_now(worker);
+        simulation.schedule_now(waker);
+        simulation.run_until_empty();
+
+        let secs = Duration::from_secs;
+        let timeline = simulation.timeline().unwrap();
+        assert_eq!(
+            vec![
+                (secs(0), secs(2), EntityState::Active),
+                (secs(2), secs(5), EntityState::Passive),
+                (secs(5), secs(6), EntityState::Active)
+            ],
+            timeline.intervals(worker)
+        );
+        assert_eq!(
+            vec![(secs(0), secs(5), EntityState::Active)],
+            timeline.intervals(waker)
+        );
     }
 }
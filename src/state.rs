@@ -8,10 +8,7 @@ pub struct StateKey<T> {
 
 impl<T> Clone for StateKey<T> {
     fn clone(&self) -> Self {
-        Self {
-            id: self.id,
-            value: PhantomData,
-        }
+        *self
     }
 }
 
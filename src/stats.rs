@@ -0,0 +1,687 @@
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use crate::{ClockRef, Key};
+
+/// Counters kept by the simulation while it runs.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SimulationStats {
+    /// Number of events popped from the scheduler and processed.
+    pub events_processed: u64,
+    /// Number of events skipped because their entity had completed or was removed.
+    /// Skipping still moves the clock to the event's time.
+    pub stale_events: u64,
+    /// Number of processed events by category, see [`Simulation::schedule_tagged`](crate::Simulation::schedule_tagged).
+    pub events_by_category: BTreeMap<u16, u64>,
+    /// Number of holds with a positive duration.
+    pub holds: u64,
+    /// Number of holds with a zero duration, used to yield to the other entities
+    /// scheduled at the same time rather than to spend time.
+    pub zero_holds: u64,
+    /// Total duration requested by the holds with a positive duration.
+    pub hold_time: Duration,
+    /// Simulation time of the first processed event.
+    pub first_event_time: Option<Duration>,
+    /// Simulation time of the last processed event.
+    pub last_event_time: Option<Duration>,
+}
+
+impl SimulationStats {
+    pub(crate) fn record_event(&mut self, time: Duration, category: u16) {
+        self.events_processed += 1;
+        self.first_event_time.get_or_insert(time);
+        self.last_event_time = Some(time);
+        *self.events_by_category.entry(category).or_default() += 1;
+    }
+
+    pub(crate) fn record_hold(&mut self, duration: Duration) {
+        if duration.is_zero() {
+            self.zero_holds += 1;
+        } else {
+            self.holds += 1;
+            self.hold_time = self.hold_time.saturating_add(duration);
+        }
+    }
+
+    /// Returns the simulation time between the first and the last processed events,
+    /// `None` before the first one.
+    #[must_use]
+    pub fn span(&self) -> Option<Duration> {
+        Some(self.last_event_time?.saturating_sub(self.first_event_time?))
+    }
+
+    /// Returns the number of processed events with the given `category`.
+    #[must_use]
+    pub fn events_in_category(&self, category: u16) -> u64 {
+        self.events_by_category
+            .get(&category)
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+/// Time-weighted statistics of a value that changes at discrete points in simulation time,
+/// like the number of units in use of a resource.
+#[derive(Debug, Clone)]
+pub struct Accumulate {
+    clock: ClockRef,
+    start: Duration,
+    last_update: Duration,
+    value: f64,
+    /// Integral of the value from `start` to `last_update`.
+    area: f64,
+    max: f64,
+}
+
+impl Accumulate {
+    /// Start tracking a value equal to `initial` at the current time of `clock`.
+    #[must_use]
+    pub fn new(clock: ClockRef, initial: f64) -> Self {
+        let now = clock.time();
+        Self {
+            clock,
+            start: now,
+            last_update: now,
+            value: initial,
+            area: 0.0,
+            max: initial,
+        }
+    }
+
+    /// Set the value from the current time onwards.
+    pub fn update(&mut self, value: f64) {
+        let now = self.clock.time();
+        self.area += self.value * now.saturating_sub(self.last_update).as_secs_f64();
+        self.last_update = now;
+        self.value = value;
+        self.max = self.max.max(value);
+    }
+
+    /// Returns the current value.
+    #[must_use]
+    pub fn value(&self) -> f64 {
+        self.value
+    }
+
+    /// Returns the largest value seen.
+    #[must_use]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Returns the time elapsed since tracking started.
+    #[must_use]
+    pub fn elapsed(&self) -> Duration {
+        self.clock.time().saturating_sub(self.start)
+    }
+
+    /// Returns the integral of the value over time in value-seconds, up to the current time.
+    #[must_use]
+    pub fn integral(&self) -> f64 {
+        let elapsed = self.clock.time().saturating_sub(self.last_update);
+        self.area + self.value * elapsed.as_secs_f64()
+    }
+
+    /// Returns the time-weighted average, the current value if no time has elapsed.
+    #[must_use]
+    pub fn mean(&self) -> f64 {
+        let elapsed = self.elapsed().as_secs_f64();
+        if elapsed > 0.0 {
+            self.integral() / elapsed
+        } else {
+            self.value
+        }
+    }
+}
+
+/// Number of events processed in consecutive intervals of simulation time.
+///
+/// Meant to be fed from a [step observer](crate::Simulation::add_step_observer).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TimeSeriesCounter {
+    bucket: Duration,
+    buckets: Vec<u64>,
+}
+
+impl TimeSeriesCounter {
+    /// Create a counter with intervals of length `bucket`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `bucket` is zero.
+    #[must_use]
+    pub fn new(bucket: Duration) -> Self {
+        assert!(
+            !bucket.is_zero(),
+            "The bucket length must be greater than zero"
+        );
+        Self {
+            bucket,
+            buckets: Vec::new(),
+        }
+    }
+
+    /// Count an event processed at `time`.
+    pub fn record(&mut self, time: Duration) {
+        let index = usize::try_from(time.as_nanos() / self.bucket.as_nanos())
+            .expect("too many buckets for the simulated time");
+        if index >= self.buckets.len() {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += 1;
+    }
+
+    /// Returns the counts per interval, up to the last interval with an event.
+    #[must_use]
+    pub fn buckets(&self) -> &[u64] {
+        &self.buckets
+    }
+
+    /// Returns the time at which the interval `index` starts.
+    #[must_use]
+    pub fn bucket_start(&self, index: usize) -> Duration {
+        self.bucket
+            .saturating_mul(u32::try_from(index).unwrap_or(u32::MAX))
+    }
+}
+
+/// Summary statistics of independent samples, like the outputs of
+/// [replications](crate::replications::run_replications).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Summary {
+    count: usize,
+    mean: f64,
+    variance: f64,
+}
+
+impl Summary {
+    /// Summarize `samples`.
+    #[must_use]
+    pub fn from_samples(samples: &[f64]) -> Self {
+        let count = samples.len();
+        let mean = samples.iter().sum::<f64>() / count as f64;
+        let variance = if count < 2 {
+            0.0
+        } else {
+            let squares: f64 = samples.iter().map(|sample| (sample - mean).powi(2)).sum();
+            squares / (count - 1) as f64
+        };
+        Self {
+            count,
+            mean,
+            variance,
+        }
+    }
+
+    /// Returns the number of samples.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the sample mean, `NaN` without samples.
+    #[must_use]
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the unbiased sample variance, zero with less than two samples.
+    #[must_use]
+    pub fn variance(&self) -> f64 {
+        self.variance
+    }
+
+    /// Returns the sample standard deviation.
+    #[must_use]
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+
+    /// Returns the bounds of the confidence interval of the mean at the given `level`,
+    /// like `0.95`, using Student's t-distribution.
+    ///
+    /// The quantiles of the t-distribution are approximated with a Cornish-Fisher
+    /// expansion, accurate to a few parts per thousand from three samples onwards.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `level` isn't between zero and one or there are less than two samples.
+    #[must_use]
+    pub fn confidence_interval(&self, level: f64) -> (f64, f64) {
+        assert!(
+            level > 0.0 && level < 1.0,
+            "The confidence level must be between zero and one"
+        );
+        assert!(
+            self.count >= 2,
+            "A confidence interval needs at least two samples"
+        );
+        let t = student_t_quantile(0.5 + level / 2.0, (self.count - 1) as f64);
+        let half_width = t * (self.variance / self.count as f64).sqrt();
+        (self.mean - half_width, self.mean + half_width)
+    }
+}
+
+/// Running statistics of observations recorded one at a time, without keeping them.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tally {
+    count: usize,
+    mean: f64,
+    /// Sum of the squared deviations from the mean.
+    squares: f64,
+    min: f64,
+    max: f64,
+}
+
+impl Tally {
+    /// Record an observation.
+    pub fn record(&mut self, value: f64) {
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+        }
+        self.count += 1;
+        // Welford's update, stable for long runs.
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.squares += delta * (value - self.mean);
+    }
+
+    /// Returns the number of observations.
+    #[must_use]
+    pub fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Returns the mean of the observations, zero without observations.
+    #[must_use]
+    pub fn mean(&self) -> f64 {
+        self.mean
+    }
+
+    /// Returns the smallest observation, zero without observations.
+    #[must_use]
+    pub fn min(&self) -> f64 {
+        self.min
+    }
+
+    /// Returns the largest observation, zero without observations.
+    #[must_use]
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    /// Returns the statistics of the observations as a [Summary].
+    #[must_use]
+    pub fn summary(&self) -> Summary {
+        let variance = if self.count < 2 {
+            0.0
+        } else {
+            self.squares / (self.count - 1) as f64
+        };
+        Summary {
+            count: self.count,
+            mean: self.mean,
+            variance,
+        }
+    }
+}
+
+/// Time spent in the system by each entity, from when it was added to the simulation
+/// until it completed, in seconds.
+///
+/// Enabled with [`Simulation::record_sojourns`](crate::Simulation::record_sojourns).
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SojournRecorder {
+    entries: BTreeMap<Key, Duration>,
+    tally: Tally,
+    histogram: Option<Histogram>,
+}
+
+impl SojournRecorder {
+    /// Create a recorder that only keeps a [Tally] of the sojourn times.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also keep the distribution of the sojourn times in a [Histogram].
+    #[must_use]
+    pub fn with_histogram(mut self) -> Self {
+        self.histogram = Some(Histogram::default());
+        self
+    }
+
+    /// Record that `key` entered the system at `time`, replacing its previous entry time.
+    pub fn enter(&mut self, key: Key, time: Duration) {
+        self.entries.insert(key, time);
+    }
+
+    /// Record that `key` left the system at `time` and return its sojourn time,
+    /// `None` if it never entered.
+    pub fn complete(&mut self, key: Key, time: Duration) -> Option<Duration> {
+        let entered = self.entries.remove(&key)?;
+        let sojourn = time.saturating_sub(entered);
+        self.tally.record(sojourn.as_secs_f64());
+        if let Some(histogram) = &mut self.histogram {
+            histogram.record(sojourn);
+        }
+        Some(sojourn)
+    }
+
+    /// Returns the running statistics of the sojourn times.
+    #[must_use]
+    pub fn tally(&self) -> &Tally {
+        &self.tally
+    }
+
+    /// Returns the distribution of the sojourn times or `None` if it isn't kept.
+    #[must_use]
+    pub fn histogram(&self) -> Option<&Histogram> {
+        self.histogram.as_ref()
+    }
+
+    /// Returns the statistics of the sojourn times, in seconds.
+    #[must_use]
+    pub fn summary(&self) -> Summary {
+        self.tally.summary()
+    }
+}
+
+/// Distribution of durations in logarithmic buckets, eight per power of two, so
+/// percentiles are within 12.5% of the recorded values whatever their scale.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+    count: u64,
+    max: Duration,
+}
+
+/// Bits of the value kept below its leading one to pick a bucket.
+const HISTOGRAM_SUB_BITS: u32 = 3;
+const HISTOGRAM_SUB_BUCKETS: u64 = 1 << HISTOGRAM_SUB_BITS;
+
+impl Histogram {
+    /// Record one sample.
+    pub fn record(&mut self, value: Duration) {
+        let nanos = u64::try_from(value.as_nanos()).unwrap_or(u64::MAX);
+        let index = Self::bucket_index(nanos);
+        if index >= self.buckets.len() {
+            self.buckets.resize(index + 1, 0);
+        }
+        self.buckets[index] += 1;
+        self.count += 1;
+        self.max = self.max.max(value);
+    }
+
+    /// Returns the number of samples recorded.
+    #[must_use]
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Returns the largest sample, zero if none were recorded.
+    #[must_use]
+    pub fn max(&self) -> Duration {
+        self.max
+    }
+
+    /// Returns the `percentile`, between 0 and 100, of the recorded samples or `None`
+    /// if there are none. The result is rounded down to the start of its bucket.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percentile` isn't between 0 and 100.
+    #[must_use]
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        assert!(
+            (0.0..=100.0).contains(&percentile),
+            "The percentile must be between 0 and 100"
+        );
+        if self.count == 0 {
+            return None;
+        }
+        if percentile == 100.0 {
+            return Some(self.max);
+        }
+        let rank = ((percentile / 100.0 * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        let index = self.buckets.iter().position(|&count| {
+            seen += count;
+            seen >= rank
+        })?;
+        Some(Duration::from_nanos(Self::bucket_start(index)).min(self.max))
+    }
+
+    fn bucket_index(nanos: u64) -> usize {
+        if nanos < HISTOGRAM_SUB_BUCKETS {
+            return nanos as usize;
+        }
+        let exponent = u64::BITS - 1 - nanos.leading_zeros();
+        let sub_bucket = (nanos >> (exponent - HISTOGRAM_SUB_BITS)) - HISTOGRAM_SUB_BUCKETS;
+        ((exponent - HISTOGRAM_SUB_BITS + 1) as u64 * HISTOGRAM_SUB_BUCKETS + sub_bucket) as usize
+    }
+
+    fn bucket_start(index: usize) -> u64 {
+        let index = index as u64;
+        if index < HISTOGRAM_SUB_BUCKETS {
+            return index;
+        }
+        let shift = index / HISTOGRAM_SUB_BUCKETS - 1;
+        (HISTOGRAM_SUB_BUCKETS + index % HISTOGRAM_SUB_BUCKETS) << shift
+    }
+}
+
+/// Wall-clock cost of processing each event, for profiling the engine itself.
+///
+/// Enabled with [`Simulation::record_profile`](crate::Simulation::record_profile).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct EngineProfiler {
+    histogram: Histogram,
+}
+
+impl EngineProfiler {
+    pub(crate) fn record(&mut self, elapsed: Duration) {
+        self.histogram.record(elapsed);
+    }
+
+    /// Returns the distribution of the time spent processing each event.
+    #[must_use]
+    pub fn histogram(&self) -> &Histogram {
+        &self.histogram
+    }
+
+    /// Returns the `percentile`, between 0 and 100, of the time spent per event.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `percentile` isn't between 0 and 100.
+    #[must_use]
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        self.histogram.percentile(percentile)
+    }
+}
+
+/// Approximates the `p` quantile of Student's t-distribution with `df` degrees of freedom.
+fn student_t_quantile(p: f64, df: f64) -> f64 {
+    let z = normal_quantile(p);
+    let z2 = z * z;
+    let g1 = (z2 + 1.0) * z / 4.0;
+    let g2 = ((5.0 * z2 + 16.0) * z2 + 3.0) * z / 96.0;
+    let g3 = (((3.0 * z2 + 19.0) * z2 + 17.0) * z2 - 15.0) * z / 384.0;
+    let g4 = ((((79.0 * z2 + 776.0) * z2 + 1482.0) * z2 - 1920.0) * z2 - 945.0) * z / 92160.0;
+    z + g1 / df + g2 / df.powi(2) + g3 / df.powi(3) + g4 / df.powi(4)
+}
+
+/// Approximates the `p` quantile of the standard normal distribution (Acklam's algorithm).
+fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < 0.024_25 {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - 0.024_25 {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::{Action, Simulation};
+
+    #[test]
+    fn tally_matches_the_summary_of_its_samples() {
+        let samples = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        let mut tally = Tally::default();
+        for sample in samples {
+            tally.record(sample);
+        }
+        let expected = Summary::from_samples(&samples);
+        let summary = tally.summary();
+        assert_eq!(expected.count(), summary.count());
+        assert!((expected.mean() - summary.mean()).abs() < 1e-12);
+        assert!((expected.variance() - summary.variance()).abs() < 1e-12);
+        assert_eq!((2.0, 9.0), (tally.min(), tally.max()));
+    }
+
+    #[test]
+    fn time_series_counts_events_per_bucket() {
+        let mut simulation = Simulation::default();
+        for hold in [1, 3] {
+            let key = simulation.add_generator(Box::new(
+                #[coroutine]
+                move |_| {
+                    for _ in 0..3 {
+                        yield Action::Hold(Duration::from_secs(hold));
+                    }
+                },
+            ));
+            simulation.schedule_now(key);
+        }
+        let counter = Rc::new(RefCell::new(TimeSeriesCounter::new(Duration::from_secs(2))));
+        let observed = Rc::clone(&counter);
+        simulation.add_step_observer(move |time, _| observed.borrow_mut().record(time));
+
+        simulation.run_until_empty();
+
+        // Events at 0, 1, 2, 3 and 0, 3, 6, 9 seconds.
+        let counter = counter.borrow();
+        assert_eq!(&[3, 3, 0, 1, 1], counter.buckets());
+        assert_eq!(Duration::from_secs(6), counter.bucket_start(3));
+    }
+
+    #[test]
+    fn summary_of_known_samples() {
+        let samples = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0, 3.0, 7.0];
+        let summary = Summary::from_samples(&samples);
+
+        assert_eq!(10, summary.count());
+        assert!((summary.mean() - 5.0).abs() < 1e-12);
+        assert!((summary.variance() - 40.0 / 9.0).abs() < 1e-12);
+
+        // t(0.975, 9) = 2.262157
+        let half_width = 2.262_157 * (40.0_f64 / 9.0 / 10.0).sqrt();
+        let (low, high) = summary.confidence_interval(0.95);
+        assert!((low - (5.0 - half_width)).abs() < 1e-3, "low = {}", low);
+        assert!((high - (5.0 + half_width)).abs() < 1e-3, "high = {}", high);
+
+        assert!((normal_quantile(0.975) - 1.959_964).abs() < 1e-6);
+        assert!((student_t_quantile(0.995, 30.0) - 2.749_996).abs() < 1e-4);
+    }
+
+    #[test]
+    fn histogram_percentiles() {
+        let mut histogram = Histogram::default();
+        assert_eq!(None, histogram.percentile(50.0));
+        for nanos in 1..=1000 {
+            histogram.record(Duration::from_nanos(nanos));
+        }
+
+        assert_eq!(1000, histogram.count());
+        assert_eq!(Some(Duration::from_nanos(1)), histogram.percentile(0.0));
+        assert_eq!(
+            Some(Duration::from_nanos(1000)),
+            histogram.percentile(100.0)
+        );
+        for (percentile, exact) in [(50.0, 500.0), (90.0, 900.0), (99.0, 990.0)] {
+            let value = histogram.percentile(percentile).unwrap().as_nanos() as f64;
+            assert!(
+                value <= exact && value > exact * 0.875,
+                "p{} = {}",
+                percentile,
+                value
+            );
+        }
+        for index in 0..200 {
+            let start = Histogram::bucket_start(index);
+            assert_eq!(index, Histogram::bucket_index(start));
+        }
+    }
+
+    #[test]
+    fn profiler_records_every_processed_event() {
+        let mut simulation = Simulation::default();
+        for hold in 1..=3 {
+            let key = simulation.add_generator(Box::new(
+                #[coroutine]
+                move |_| {
+                    for _ in 0..4 {
+                        yield Action::Hold(Duration::from_secs(hold));
+                    }
+                },
+            ));
+            simulation.schedule_now(key);
+        }
+        assert!(simulation.profiler().is_none());
+        simulation.record_profile();
+
+        simulation.run_until_empty();
+
+        let profiler = simulation.profiler().unwrap();
+        assert_eq!(15, simulation.stats().events_processed);
+        assert_eq!(15, profiler.histogram().count());
+        assert!(profiler.percentile(50.0).unwrap() <= profiler.histogram().max());
+    }
+}
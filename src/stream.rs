@@ -0,0 +1,142 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::Stream;
+
+use crate::{Key, ShouldContinue, Simulation};
+
+/// How many items [StepStream] yields before returning [`Poll::Pending`] once.
+const YIELD_EVERY: usize = 64;
+
+/// Stream that processes one event per item, see [`Simulation::step_stream`].
+///
+/// Each item is the time and [Key] of the processed event, the stream ends once
+/// no events are left.
+#[derive(Debug)]
+pub struct StepStream<'a> {
+    simulation: &'a mut Simulation<()>,
+    /// Items yielded since the last [`Poll::Pending`].
+    since_yield: usize,
+}
+
+impl Stream for StepStream<'_> {
+    type Item = (Duration, Key);
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        if self.since_yield == YIELD_EVERY {
+            // Give the executor a chance to run other tasks, it polls again right away.
+            self.since_yield = 0;
+            cx.waker().wake_by_ref();
+            return Poll::Pending;
+        }
+        loop {
+            let next = self.simulation.scheduler.peek();
+            let before = self.simulation.stats().events_processed;
+            match self.simulation.step() {
+                ShouldContinue::Break => return Poll::Ready(None),
+                // The next step processes the event.
+                ShouldContinue::Breakpoint(_) => {}
+                ShouldContinue::Advance => {
+                    // The idle hook scheduled new work or a stale event was skipped.
+                    if self.simulation.stats().events_processed > before {
+                        self.since_yield += 1;
+                        return Poll::Ready(next);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Simulation<()> {
+    /// Returns a [Stream] that processes one event every time it is polled.
+    ///
+    /// Stepping never waits, but every 64 events the stream returns [`Poll::Pending`]
+    /// once and wakes itself, so awaiting the items lets other tasks on the same
+    /// executor run between events.
+    pub fn step_stream(&mut self) -> StepStream<'_> {
+        StepStream {
+            simulation: self,
+            since_yield: 0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use futures::executor::block_on;
+    use futures::task::noop_waker_ref;
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{Action, EntityState};
+
+    #[test]
+    fn stream_yields_every_event() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                for _ in 0..2 {
+                    yield Action::Hold(Duration::from_secs(1));
+                }
+            },
+        ));
+        simulation.schedule_now(key);
+
+        let events: Vec<(Duration, Key)> = block_on(simulation.step_stream().collect());
+
+        let secs = Duration::from_secs;
+        assert_eq!(vec![(secs(0), key), (secs(1), key), (secs(2), key)], events);
+        assert_eq!(Some(EntityState::Completed), simulation.entity_state(key));
+    }
+
+    #[test]
+    fn stream_yields_to_the_executor() {
+        let mut simulation = Simulation::default();
+        let key = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                for _ in 0..YIELD_EVERY * 2 {
+                    yield Action::Hold(Duration::from_secs(1));
+                }
+            },
+        ));
+        simulation.schedule_now(key);
+
+        let mut stream = simulation.step_stream();
+        let mut cx = Context::from_waker(noop_waker_ref());
+        let (mut items, mut pending) = (0, 0);
+        loop {
+            match Pin::new(&mut stream).poll_next(&mut cx) {
+                Poll::Ready(Some(_)) => items += 1,
+                Poll::Ready(None) => break,
+                Poll::Pending => pending += 1,
+            }
+        }
+
+        assert_eq!(YIELD_EVERY * 2 + 1, items);
+        assert_eq!(2, pending);
+    }
+
+    #[test]
+    fn stream_skips_stale_events() {
+        let mut simulation = Simulation::default();
+        let timeouts = simulation.add_queue("timeouts");
+        let key = simulation.add_generator(Box::new(
+            #[coroutine]
+            |_| {
+                yield Action::Hold(Duration::from_secs(1));
+            },
+        ));
+        simulation.schedule_now(key);
+        simulation.schedule_in_queue(timeouts, Duration::from_secs(5), key);
+
+        let events: Vec<(Duration, Key)> = block_on(simulation.step_stream().collect());
+
+        let secs = Duration::from_secs;
+        assert_eq!(vec![(secs(0), key), (secs(1), key)], events);
+        assert_eq!(1, simulation.stats().stale_events);
+    }
+}
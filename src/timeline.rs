@@ -0,0 +1,44 @@
+use std::time::Duration;
+
+use crate::container::EntityState;
+use crate::keys::Key;
+
+/// History of the state changes of the entities, in the order they happened.
+///
+/// Enabled with [`Simulation::record_timeline`](crate::Simulation::record_timeline).
+/// Entities added after enabling it start with their initial state, the ones added
+/// before appear from their first change.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct StateTimeline {
+    transitions: Vec<(Key, Duration, EntityState)>,
+}
+
+impl StateTimeline {
+    pub(crate) fn record(&mut self, key: Key, time: Duration, state: EntityState) {
+        self.transitions.push((key, time, state));
+    }
+
+    /// Returns every recorded change as `(key, time, new state)`.
+    #[must_use]
+    pub fn transitions(&self) -> &[(Key, Duration, EntityState)] {
+        &self.transitions
+    }
+
+    /// Returns the `(start, end, state)` spans of the entity associated with `key`.
+    ///
+    /// Only spans that ended are returned, the current state of an entity that
+    /// hasn't completed is left out.
+    #[must_use]
+    pub fn intervals(&self, key: Key) -> Vec<(Duration, Duration, EntityState)> {
+        let changes: Vec<(Duration, EntityState)> = self
+            .transitions
+            .iter()
+            .filter(|&&(other, _, _)| other == key)
+            .map(|&(_, time, state)| (time, state))
+            .collect();
+        changes
+            .windows(2)
+            .map(|pair| (pair[0].0, pair[1].0, pair[0].1))
+            .collect()
+    }
+}